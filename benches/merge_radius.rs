@@ -0,0 +1,122 @@
+//! Benchmarks for merging trips into stop lists and for the radius/diameter calculation, on
+//! synthetic feeds of various sizes.
+//!
+//! The crate is a binary, so the modules under test are included directly by path. Merging is
+//! benchmarked through the same DAG flattening that `merge::stops_by_route` uses for each
+//! route/direction pair.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use geo::Point;
+
+#[allow(dead_code)]
+#[path = "../src/merge/dag.rs"]
+mod dag;
+#[allow(dead_code)]
+#[path = "../src/radius.rs"]
+mod radius;
+
+/// The numbers of routes in each benchmark scenario.
+const SCENARIOS: [usize; 2] = [100, 1000];
+const TRIPS_PER_ROUTE: usize = 20;
+const STOPS_PER_ROUTE: usize = 40;
+
+/// A synthetic route: its stops' locations, and its trips as sequences of stops.
+struct Route {
+    points: Vec<Point>,
+    trips: Vec<Vec<Arc<usize>>>,
+}
+
+/// A small linear congruential generator, so that the synthetic feeds are reproducible.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        self.next() as f64 / (1u64 << 31) as f64
+    }
+}
+
+/// Generates `num_routes` routes within a city-sized area, each of whose trips serves a
+/// contiguous run of the route's stops, skipping some along the way, so that merging them
+/// produces branches.
+fn synthetic_feed(num_routes: usize, trips: usize, stops: usize) -> Vec<Route> {
+    let mut rng = Lcg(num_routes as u64);
+    (0..num_routes)
+        .map(|_| {
+            let points = (0..stops)
+                .map(|_| Point::new(-122.5 + 0.3 * rng.next_f64(), 37.6 + 0.2 * rng.next_f64()))
+                .collect();
+            let stop_ids = (0..stops).map(Arc::new).collect::<Vec<_>>();
+            let trips = (0..trips)
+                .map(|_| {
+                    let start = rng.next() as usize % (stops / 4);
+                    let end = stops - rng.next() as usize % (stops / 4);
+                    stop_ids[start..end]
+                        .iter()
+                        .filter(|_| rng.next_f64() >= 0.125)
+                        .cloned()
+                        .collect()
+                })
+                .collect();
+            Route { points, trips }
+        })
+        .collect()
+}
+
+fn merge_route(route: &Route) -> Vec<(Arc<usize>, usize)> {
+    let mut dag = dag::Dag::<dag::PtrKey<usize>, Arc<usize>>::new();
+    for trip in &route.trips {
+        let mut parent = None;
+        for stop in trip {
+            dag.insert_child(parent, stop.clone()).unwrap();
+            parent = Some(stop.clone());
+        }
+    }
+    dag.flatten_with_levels().unwrap()
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    group.sample_size(10);
+    for num_routes in SCENARIOS {
+        let feed = synthetic_feed(num_routes, TRIPS_PER_ROUTE, STOPS_PER_ROUTE);
+        group.bench_with_input(BenchmarkId::from_parameter(num_routes), &feed, |b, feed| {
+            b.iter(|| feed.iter().map(merge_route).collect::<Vec<_>>())
+        });
+    }
+    group.finish();
+}
+
+fn bench_radius(c: &mut Criterion) {
+    let mut group = c.benchmark_group("radius_and_diameter");
+    group.sample_size(10);
+    for num_routes in SCENARIOS {
+        let feed = synthetic_feed(num_routes, TRIPS_PER_ROUTE, STOPS_PER_ROUTE);
+        group.bench_with_input(BenchmarkId::from_parameter(num_routes), &feed, |b, feed| {
+            b.iter(|| {
+                feed.iter()
+                    .map(|route| {
+                        radius::radius_and_diameter(
+                            black_box(&route.points),
+                            radius::Metric::Geodesic,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_merge, bench_radius);
+criterion_main!(benches);
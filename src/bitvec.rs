@@ -8,7 +8,7 @@ const BITS: usize = 8;
 
 impl BitVec {
     pub fn with_size(len: usize) -> Self {
-        let num_bytes = (len + BITS - 1) / BITS;
+        let num_bytes = len.div_ceil(BITS);
         BitVec {
             bytes: vec![0; num_bytes],
             len,
@@ -28,6 +28,131 @@ impl BitVec {
         self.bytes[byte] |= 1 << rem;
     }
 
+    /// Appends a bit to the end.
+    pub fn push(&mut self, bit: bool) {
+        // the last byte is full
+        if self.len == self.bytes.len() * BITS {
+            self.bytes.push(0);
+        }
+        self.len += 1;
+        if bit {
+            self.set(self.len - 1);
+        }
+    }
+
+    /// Returns whether the bit at `idx` is set.
+    pub fn get(&self, idx: usize) -> bool {
+        if idx >= self.len {
+            panic!(
+                "index {} out of bounds for BitVec of length {}",
+                idx, self.len
+            );
+        }
+        let byte = idx / BITS;
+        let rem = BITS - 1 - (idx % BITS);
+
+        self.bytes[byte] & (1 << rem) != 0
+    }
+
+    /// Returns the number of set bits.
+    pub fn count_ones(&self) -> usize {
+        self.bytes
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the bitwise OR of all the given `BitVec`s, or `None` if there are none.
+    ///
+    /// Panics if the `BitVec`s are not all the same length.
+    pub fn union_all<'a>(iter: impl IntoIterator<Item = &'a BitVec>) -> Option<BitVec> {
+        Self::fold_all(iter, Self::union)
+    }
+
+    /// Returns the bitwise AND of all the given `BitVec`s, or `None` if there are none.
+    ///
+    /// Panics if the `BitVec`s are not all the same length.
+    pub fn intersection_all<'a>(iter: impl IntoIterator<Item = &'a BitVec>) -> Option<BitVec> {
+        Self::fold_all(iter, Self::intersection)
+    }
+
+    /// Returns the bitwise OR of two `BitVec`s.
+    ///
+    /// Panics if the `BitVec`s are not the same length.
+    pub fn union(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, "union", |a, b| a | b)
+    }
+
+    /// Returns the bitwise AND of two `BitVec`s.
+    ///
+    /// Panics if the `BitVec`s are not the same length.
+    pub fn intersection(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, "intersection", |a, b| a & b)
+    }
+
+    /// Returns the bits which are set in `self` but not in `other`.
+    ///
+    /// Panics if the `BitVec`s are not the same length.
+    pub fn difference(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, "difference", |a, b| a & !b)
+    }
+
+    /// Returns the number of positions at which the two `BitVec`s differ.
+    ///
+    /// Panics if the `BitVec`s are not the same length.
+    pub fn hamming_distance(&self, other: &BitVec) -> usize {
+        self.check_len(other, "Hamming distance");
+        self.bytes
+            .iter()
+            .zip(&other.bytes)
+            .map(|(a, b)| (a ^ b).count_ones() as usize)
+            .sum()
+    }
+
+    fn fold_all<'a>(
+        iter: impl IntoIterator<Item = &'a BitVec>,
+        f: impl Fn(&BitVec, &BitVec) -> BitVec,
+    ) -> Option<BitVec> {
+        let mut iter = iter.into_iter();
+        let first = iter.next()?.clone();
+        Some(iter.fold(first, |acc, other| f(&acc, other)))
+    }
+
+    /// Combines two `BitVec`s byte-wise.
+    fn zip_with(&self, other: &BitVec, op: &str, f: impl Fn(u8, u8) -> u8) -> BitVec {
+        self.check_len(other, op);
+        BitVec {
+            bytes: self
+                .bytes
+                .iter()
+                .zip(&other.bytes)
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+            len: self.len,
+        }
+    }
+
+    fn check_len(&self, other: &BitVec, op: &str) {
+        if other.len != self.len {
+            panic!(
+                "length mismatch in {op} of BitVecs ({} and {})",
+                self.len, other.len
+            );
+        }
+    }
+
+    /// Returns an iterator over the indices of the set bits, in increasing order.
+    ///
+    /// Empty bytes are skipped whole, so this is fast for sparse `BitVec`s.
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        let bytes = self.bytes.iter().enumerate().filter(|(_, &byte)| byte != 0);
+        bytes.flat_map(|(i, &byte)| {
+            (0..BITS)
+                .filter(move |bit| byte & (1 << (BITS - 1 - bit)) != 0)
+                .map(move |bit| i * BITS + bit)
+        })
+    }
+
     pub fn to_vec(&self) -> Vec<bool> {
         let mut vec = vec![false; self.len];
         for (i, byte) in self.bytes.iter().enumerate() {
@@ -40,3 +165,81 @@ impl BitVec {
         vec
     }
 }
+
+impl FromIterator<bool> for BitVec {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        let mut bitvec = BitVec::with_size(0);
+        for bit in iter {
+            bitvec.push(bit);
+        }
+        bitvec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(bits: &str) -> BitVec {
+        bits.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn union_all() {
+        let patterns = [bits("1100000001"), bits("0110000000"), bits("0000100001")];
+        assert_eq!(BitVec::union_all(&patterns), Some(bits("1110100001")));
+        assert_eq!(BitVec::union_all(&[]), None);
+    }
+
+    #[test]
+    fn push_across_byte_boundaries() {
+        let expected = "10110011100011110"
+            .chars()
+            .map(|c| c == '1')
+            .collect::<Vec<_>>();
+        let mut pushed = BitVec::with_size(0);
+        for &bit in &expected {
+            pushed.push(bit);
+        }
+        assert_eq!(pushed.len, 17);
+        assert_eq!(pushed.bytes.len(), 3);
+        assert_eq!(pushed.to_vec(), expected);
+
+        let collected = expected.iter().copied().collect::<BitVec>();
+        assert_eq!(collected, pushed);
+    }
+    #[test]
+    fn iter_ones_and_count_ones() {
+        let check = |bitvec: &BitVec| {
+            let expected = (0..bitvec.len)
+                .filter(|&i| bitvec.get(i))
+                .collect::<Vec<_>>();
+            assert_eq!(bitvec.iter_ones().collect::<Vec<_>>(), expected);
+            assert_eq!(bitvec.count_ones(), expected.len());
+        };
+        // an empty middle byte, bits on either side of each boundary, and a partial last byte
+        let patterns = [
+            "",
+            "1",
+            "00000000",
+            "10000001",
+            "0000000110000000000000011",
+            "00000000000000000000001",
+            "1111111111111111111",
+        ];
+        for pattern in patterns {
+            check(&bits(pattern));
+        }
+
+        // the padding bits in the last byte stay clear through the bitwise operations
+        let a = bits("1011000000000000011");
+        let b = bits("0110000000000000101");
+        for bitvec in [a.union(&b), a.intersection(&b), a.difference(&b)] {
+            check(&bitvec);
+        }
+        assert_eq!(a.difference(&b).iter_ones().collect::<Vec<_>>(), [0, 3, 17]);
+    }
+}
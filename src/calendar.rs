@@ -0,0 +1,116 @@
+//! Service calendar lookups, from `calendar.txt` and `calendar_dates.txt`.
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use gtfs_structures::{Calendar, Exception, Gtfs};
+
+/// Returns whether the service runs on the given day of the week.
+///
+/// For services in `calendar.txt`, this uses the weekly schedule. Services defined only by
+/// `calendar_dates.txt` run on a day of the week if any of their added dates fall on that day.
+///
+/// Returns `None` if the service isn't in either file.
+pub fn runs_on_weekday(gtfs: &Gtfs, service_id: &str, weekday: Weekday) -> Option<bool> {
+    if let Some(calendar) = gtfs.calendar.get(service_id) {
+        return Some(calendar_weekday(calendar, weekday));
+    }
+    let dates = added_dates(gtfs, service_id)?;
+    Some(dates.iter().any(|date| date.weekday() == weekday))
+}
+
+/// Logs the range and number of dates of each service which is only in `calendar_dates.txt`, and
+/// so whose days of the week [`runs_on_weekday`] infers from its dates.
+pub fn log_date_only_services(gtfs: &Gtfs) {
+    let mut service_ids = gtfs
+        .calendar_dates
+        .keys()
+        .filter(|id| !gtfs.calendar.contains_key(*id))
+        .collect::<Vec<_>>();
+    service_ids.sort();
+    for service_id in service_ids {
+        let dates = added_dates(gtfs, service_id).unwrap_or_default();
+        if let (Some(first), Some(last)) = (dates.iter().min(), dates.iter().max()) {
+            log::info!(
+                "service {service_id} is only in calendar_dates.txt: {} dates from {first} to \
+                {last}",
+                dates.len()
+            );
+        }
+    }
+}
+
+/// Returns whether the service runs on the given date, or `None` if the service isn't in
+/// `calendar.txt` or `calendar_dates.txt`.
+pub fn runs_on_date(gtfs: &Gtfs, service_id: &str, date: NaiveDate) -> Option<bool> {
+    if !gtfs.calendar.contains_key(service_id) && !gtfs.calendar_dates.contains_key(service_id) {
+        return None;
+    }
+    Some(active_in_range(gtfs, service_id, date, date))
+}
+
+/// Returns whether the service runs on any date from `start` to `end`, inclusive.
+///
+/// This takes into account both the weekly schedule in `calendar.txt` and the added and removed
+/// dates in `calendar_dates.txt`.
+pub fn active_in_range(gtfs: &Gtfs, service_id: &str, start: NaiveDate, end: NaiveDate) -> bool {
+    let exceptions = gtfs
+        .calendar_dates
+        .get(service_id)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let is_deleted = |date| {
+        exceptions.iter().any(|exception| {
+            exception.date == date && matches!(exception.exception_type, Exception::Deleted)
+        })
+    };
+    if exceptions.iter().any(|date| {
+        matches!(date.exception_type, Exception::Added) && (start..=end).contains(&date.date)
+    }) {
+        return true;
+    }
+    let Some(calendar) = gtfs.calendar.get(service_id) else {
+        return false;
+    };
+    let last = end.min(calendar.end_date);
+    start
+        .max(calendar.start_date)
+        .iter_days()
+        .take_while(|date| *date <= last)
+        .any(|date| calendar_weekday(calendar, date.weekday()) && !is_deleted(date))
+}
+
+/// Returns the dates added to the service in `calendar_dates.txt`, or `None` if the service isn't
+/// in `calendar_dates.txt`.
+fn added_dates(gtfs: &Gtfs, service_id: &str) -> Option<Vec<NaiveDate>> {
+    let dates = gtfs.calendar_dates.get(service_id)?;
+    Some(
+        dates
+            .iter()
+            .filter(|date| matches!(date.exception_type, Exception::Added))
+            .map(|date| date.date)
+            .collect(),
+    )
+}
+
+/// Returns the dates on which the service is added or removed in `calendar_dates.txt`.
+pub fn exception_dates<'a>(
+    gtfs: &'a Gtfs,
+    service_id: &str,
+) -> impl Iterator<Item = NaiveDate> + 'a {
+    gtfs.calendar_dates
+        .get(service_id)
+        .into_iter()
+        .flatten()
+        .map(|date| date.date)
+}
+
+fn calendar_weekday(calendar: &Calendar, weekday: Weekday) -> bool {
+    match weekday {
+        Weekday::Mon => calendar.monday,
+        Weekday::Tue => calendar.tuesday,
+        Weekday::Wed => calendar.wednesday,
+        Weekday::Thu => calendar.thursday,
+        Weekday::Fri => calendar.friday,
+        Weekday::Sat => calendar.saturday,
+        Weekday::Sun => calendar.sunday,
+    }
+}
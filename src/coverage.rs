@@ -0,0 +1,63 @@
+//! Estimates the area within walking distance of a set of points, in km²
+//!
+//! Each point is buffered by a circle of the walking distance (approximated by a regular polygon),
+//! and the area of the union of the circles is measured. Overlapping circles are only counted
+//! once.
+//!
+//! The points are projected onto a plane using an equirectangular projection centered on their
+//! mean latitude. This is accurate to within a fraction of a percent for areas the size of a
+//! city, but becomes less accurate for points spread over hundreds of km, or near the poles.
+
+use geo::{Area, BooleanOps, Coord, LineString, MultiPolygon, Point, Polygon};
+
+/// Mean radius of the earth, in km.
+const EARTH_RADIUS: f64 = 6371.0088;
+
+/// Number of sides of the polygon used to approximate each circle.
+const CIRCLE_SIDES: usize = 32;
+
+/// Returns the area, in km², within `walk_distance` km of any of the points.
+pub fn coverage_area(points: &[Point], walk_distance: f64) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let mean_lat = points.iter().map(|p| p.y()).sum::<f64>() / points.len() as f64;
+    let x_scale = EARTH_RADIUS * mean_lat.to_radians().cos();
+
+    let mut circles = points
+        .iter()
+        .map(|p| {
+            let center = Coord {
+                x: p.x().to_radians() * x_scale,
+                y: p.y().to_radians() * EARTH_RADIUS,
+            };
+            MultiPolygon::new(vec![circle(center, walk_distance)])
+        })
+        .collect::<Vec<_>>();
+
+    // union pairwise, so that each union operates on polygons of similar complexity
+    while circles.len() > 1 {
+        circles = circles
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => a.union(b),
+                [a] => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    circles[0].unsigned_area()
+}
+
+fn circle(center: Coord, radius: f64) -> Polygon {
+    let ring = (0..CIRCLE_SIDES)
+        .map(|i| {
+            let theta = std::f64::consts::TAU * i as f64 / CIRCLE_SIDES as f64;
+            Coord {
+                x: center.x + radius * theta.cos(),
+                y: center.y + radius * theta.sin(),
+            }
+        })
+        .collect::<LineString>();
+    Polygon::new(ring, Vec::new())
+}
@@ -0,0 +1,68 @@
+//! Line-by-line diffs of sequences.
+
+/// A line of a diff.
+#[derive(Debug)]
+pub enum Change<T> {
+    /// In both sequences.
+    Same(T),
+    /// Only in the old sequence.
+    Removed(T),
+    /// Only in the new sequence.
+    Added(T),
+}
+
+impl<T> Change<T> {
+    /// Returns the marker for the change, as in a unified diff.
+    pub fn marker(&self) -> char {
+        match self {
+            Change::Same(_) => ' ',
+            Change::Removed(_) => '-',
+            Change::Added(_) => '+',
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        match self {
+            Change::Same(value) | Change::Removed(value) | Change::Added(value) => value,
+        }
+    }
+}
+
+/// Diffs two sequences, keeping a longest common subsequence of them the same, and marking the
+/// rest of the old sequence as removed and the rest of the new sequence as added.
+pub fn diff_by<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: impl Fn(&T, &T) -> bool,
+) -> Vec<Change<&'a T>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if eq(&old[i], &new[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if eq(&old[i], &new[j]) {
+            changes.push(Change::Same(&old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            changes.push(Change::Removed(&old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&new[j]));
+            j += 1;
+        }
+    }
+    changes.extend(old[i..].iter().map(Change::Removed));
+    changes.extend(new[j..].iter().map(Change::Added));
+    changes
+}
@@ -1,36 +1,363 @@
 use std::collections::HashSet;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use gtfs_structures::Gtfs;
 
 mod bitvec;
+mod calendar;
+mod coverage;
+mod diff;
 mod merge;
 mod multimap;
+mod output;
 mod radius;
+mod report;
+mod shape;
 mod table;
+mod transfers;
 mod types;
+mod validate;
 
 #[derive(Debug, Parser)]
 pub struct Args {
+    /// The GTFS feed: a local directory or zip file, the `http://` or `https://` URL of a zip
+    /// file, or `-` to read a zip file from stdin.
     source: String,
     #[clap(subcommand)]
     command: Command,
     /// Only include routes with specified `agency_id`s
     #[clap(long, value_delimiter = ',')]
     agency: Option<Vec<String>>,
-    /// Only include routes with specified `route_id`s
+    /// Only include routes with specified `route_id`s. May be repeated, or given as a
+    /// comma-separated list.
+    ///
+    /// Ids not in the feed are warned about, but it is an error if none of them are.
+    #[clap(long, value_delimiter = ',')]
+    route: Vec<String>,
+    /// Match `--agency` and `--route` ids case-insensitively.
+    #[clap(long)]
+    ignore_case: bool,
+    /// Only include trips whose service runs on the given day of the week (e.g. `mon`).
+    ///
+    /// Services defined only by `calendar_dates.txt` run on a day of the week if any of their
+    /// added dates fall on that day.
+    #[clap(long)]
+    weekday: Option<chrono::Weekday>,
+    /// Only include trips whose service runs on at least one day on or after this date (e.g.
+    /// `2024-06-01`).
+    #[clap(long)]
+    start_date: Option<chrono::NaiveDate>,
+    /// Only include trips whose service runs on at least one day on or before this date (e.g.
+    /// `2024-08-31`).
+    #[clap(long)]
+    end_date: Option<chrono::NaiveDate>,
+    /// Remove the stops with these `stop_id`s from every trip before any analysis, e.g. to ignore
+    /// a garage or deadhead stop. May be repeated.
+    ///
+    /// This can change the merged order of a route's stops.
     #[clap(long, value_delimiter = ',')]
-    route: Option<Vec<String>>,
+    exclude_stop: Vec<String>,
+    /// Only include trips whose service runs on this date (e.g. `2024-06-03`), taking into account
+    /// the exceptions in `calendar_dates.txt`.
+    ///
+    /// Trips whose service isn't in either calendar file are dropped.
+    #[clap(long)]
+    service_date: Option<chrono::NaiveDate>,
+    /// Only include trips whose `trip_headsign` contains this text, ignoring case.
+    #[clap(long)]
+    headsign: Option<String>,
+    /// A TOML file of display names to use instead of the feed's names, with a `[routes]` table
+    /// mapping `route_id`s to names, and a `[stops]` table mapping `stop_id`s to names.
+    ///
+    /// Ids not listed in the file keep the feed's names.
+    #[clap(long)]
+    names: Option<std::path::PathBuf>,
+    #[clap(skip)]
+    name_overrides: types::NameOverrides,
     /// Use the `short_name` instead of `long_name` when displaying route names.
     #[clap(long)]
     use_short_name: bool,
+    /// Append the route's `route_desc`, if any, to each section heading.
+    #[clap(long)]
+    show_desc: bool,
+    /// Truncate route descriptions longer than this many characters.
+    #[clap(long, default_value_t = 60)]
+    desc_max_len: usize,
+    /// Show each stop's rider-facing `stop_code`, where present, alongside its name.
+    #[clap(long)]
+    show_stop_code: bool,
+    /// Print an explicit note for route/direction pairs with no data, rather than an empty list
+    /// or table.
+    #[clap(long)]
+    note_empty: bool,
+    /// In `route-summary`, mark the first stop with "(start)" and the last with "(end)".
+    ///
+    /// Only the ends of the merged list are marked; on branched routes, the other branches'
+    /// ends are not.
+    #[clap(long)]
+    mark_termini: bool,
+    /// In `stopping-patterns`, mark stops served by some but not all of the stopping patterns
+    /// with "(branch)".
+    #[clap(long)]
+    mark_branch_stops: bool,
+    /// In `stopping-patterns`, omit patterns with fewer than this many trips, noting how many
+    /// were omitted below the table.
+    #[clap(long, default_value_t = 1)]
+    min_trips: usize,
+    /// How to order the columns of `stopping-patterns`.
+    #[clap(long, value_enum, default_value_t = PatternSort::Count)]
+    pattern_sort: PatternSort,
+    /// In `route-summary`, show each stop's distance along the route from the first stop, in km,
+    /// following the merged stop order.
+    ///
+    /// Stops without coordinates are shown with the distance to the previous stop which has
+    /// coordinates, and marked as such.
+    #[clap(long)]
+    show_distance: bool,
+    /// In `route-summary`, list stops by the number of trips serving them, most-served first,
+    /// and show that number after each stop.
+    ///
+    /// This discards the stops' order along the route, so it doesn't combine usefully with
+    /// `--show-distance` or `--mark-termini`.
+    #[clap(long)]
+    sort_by_coverage: bool,
+    /// In `route-summary`, show each stop's depth in the merged graph of the route's trips: the
+    /// greatest number of stops before it on any trip's path. Stops at the same depth are on
+    /// parallel branches, whose relative order in the list is arbitrary.
+    #[clap(long)]
+    show_depth: bool,
+    /// In `time-table`, show times as minutes elapsed since each trip's first departure, rather
+    /// than as clock times.
+    ///
+    /// If any trip on a route/direction has no time at its first stop, that route/direction
+    /// falls back to clock times.
+    #[clap(long)]
+    relative_times: bool,
+    /// In `time-table`, split each route/direction's timetable into separate AM and PM tables,
+    /// by each trip's first departure, as in a printed schedule. Trips with no first departure
+    /// go in the AM table.
+    #[clap(long)]
+    fold: bool,
+    /// In `route-summary` and `time-table`, order stops by their position along the route's most
+    /// common shape, rather than by merging the trips' stop orders.
+    ///
+    /// Route/direction pairs without a shape keep the merged order.
+    #[clap(long)]
+    order_by_shape: bool,
+    /// In `stopping-patterns`, instead of a table, list the indices (into the route/direction's
+    /// stop list) of the stops served by each pattern, along with a legend mapping indices to
+    /// stop ids.
+    ///
+    /// In CSV output, the legend for each route/direction is given as `#`-prefixed comment
+    /// lines.
+    #[clap(long, value_enum)]
+    pattern_indices: Option<IndexFormat>,
+    /// In `stopping-patterns` and `time-table`, show each pattern or trip as a row and each stop
+    /// as a column, rather than the reverse.
+    ///
+    /// This is more compact for routes with many stops but few patterns or trips, but the table
+    /// gets very wide, and stop names are harder to read as column headers.
+    #[clap(long)]
+    transpose: bool,
+    /// In `pattern-clusters`, the greatest number of stops by which two patterns can differ and
+    /// still be grouped together.
+    #[clap(long, default_value_t = 2)]
+    cluster_distance: usize,
+    /// In `time-table` and `stopping-patterns`, match trips' stops to the route's stops by
+    /// `stop_id` when they aren't the same in-memory stop.
+    ///
+    /// This is only needed if a stop has been loaded more than once, and makes matching slower.
+    #[clap(long)]
+    match_by_id: bool,
     /// Use `trip_short_name` to determine direction: odd-numbered trips are outbound,
     /// even-numbered are inbound.
     #[clap(long)]
     direction_from_trip_name: bool,
+    /// Use a regular expression on `trip_short_name` to determine direction: the text matched by
+    /// its first capture group is looked up in `--inbound-codes` and `--outbound-codes`.
+    ///
+    /// Trips whose name doesn't match, or whose code is in neither list, fall back to
+    /// `direction_id`. For example, `--direction-regex '([NS])$' --inbound-codes S
+    /// --outbound-codes N`.
+    #[clap(long, value_parser = regex::Regex::new, conflicts_with = "direction_from_trip_name")]
+    direction_regex: Option<regex::Regex>,
+    /// Codes captured by `--direction-regex` which mean inbound.
+    #[clap(long, value_delimiter = ',', requires = "direction_regex")]
+    inbound_codes: Vec<String>,
+    /// Codes captured by `--direction-regex` which mean outbound.
+    #[clap(long, value_delimiter = ',', requires = "direction_regex")]
+    outbound_codes: Vec<String>,
+    /// Swap inbound and outbound, for feeds whose directions are consistently backwards from the
+    /// agency's convention.
+    #[clap(long)]
+    reverse_directions: bool,
+    /// Split each route/direction pair by `trip_headsign`, so that branches sharing a route and
+    /// direction get their own sections.
+    #[clap(long)]
+    group_by_headsign: bool,
     /// Combine trips from all selected routes as if they were a single route.
     #[clap(long)]
     merge_routes: bool,
+    /// Order each trip's stop times by this key before merging trips, in case the feed lists them
+    /// out of order.
+    #[clap(long, value_enum, default_value_t = StopTimeOrder::Sequence)]
+    order_by: StopTimeOrder,
+    /// Warn about stops in a route/direction's merged stop list which have the same name but
+    /// different ids, which show up as the same stop twice.
+    #[clap(long)]
+    warn_duplicate_names: bool,
+    /// Merge stops with the same name and location (to within about 10 m) into one, for feeds
+    /// which duplicate stops, e.g. for each direction.
+    #[clap(long)]
+    collapse_same_stop_name: bool,
+    /// Ignore trips with fewer than two stops, or with stops missing from the feed's stops.
+    #[clap(long)]
+    drop_incomplete_trips: bool,
+    /// Emit one combined table spanning all routes, with a route column, instead of a section
+    /// per route/direction pair. Currently only affects `route-summary`.
+    #[clap(long)]
+    single_table: bool,
+    /// Distance, in meters, within which `validate` reports distinct stops as possible
+    /// duplicates.
+    #[clap(long, default_value_t = 1.0)]
+    duplicate_distance: f64,
+    /// Warn about route/direction pairs whose shortest trip has less than this fraction of the
+    /// stops of its longest trip, such as 0.3, which suggests short-turn trips or mixed service.
+    /// Off by default.
+    ///
+    /// `stop-counts` shows the full distribution.
+    #[clap(long)]
+    short_trip_ratio: Option<f64>,
+    /// Speed, in km/h, above which `speed-check` flags a segment.
+    #[clap(long, default_value_t = 150.0)]
+    max_speed: f64,
+    /// How to measure distances between stops.
+    #[clap(long, alias = "distance-metric", value_enum, default_value_t)]
+    metric: radius::Metric,
+    /// How many routes to list in each category of `leaderboard`.
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+    /// How to order the rows of `radius-diameter`. Radius and diameter are sorted largest first.
+    #[clap(long, value_enum, default_value_t = SortBy::Name)]
+    sort_by: SortBy,
+    /// In `radius-diameter`, the number of threads to compute routes on in parallel. Defaults to
+    /// the number of CPUs.
+    #[clap(long)]
+    jobs: Option<usize>,
+    /// Walking distance, in meters, from each stop used by `coverage`, and between stops used by
+    /// `nearby-transfers`.
+    #[clap(long, default_value_t = 400.0)]
+    walk_distance: f64,
+    /// Order output by agency, then by route name, with a heading for each agency.
+    #[clap(long)]
+    group_by_agency: bool,
+    /// Order output by route type (bus, subway, etc.), then by route name, with a heading for
+    /// each route type.
+    #[clap(long, conflicts_with = "group_by_agency")]
+    group_by_type: bool,
+    /// Print a linked table of contents of all route/direction headings before the output. Only
+    /// applies to markdown output.
+    #[clap(long)]
+    toc: bool,
+    /// The format to print tables, headings, and lists in.
+    ///
+    /// This applies to `route-summary`, `time-table`, `stopping-patterns`, `pattern-clusters`,
+    /// `radius-diameter`, `service-levels`, and `service-calendar`, and other commands reject any
+    /// format but markdown. JSON is only supported by `route-summary`.
+    #[clap(long, value_enum, default_value_t)]
+    format: table::Format,
+    /// In `route-summary`, write each route/direction pair to its own file in `--split-output`,
+    /// once in each of the given formats, e.g. `--formats md,json,csv`.
+    #[clap(long, value_enum, value_delimiter = ',', requires = "split_output")]
+    formats: Vec<table::Format>,
+    /// The directory to write the files for `--formats` to. It is created if it doesn't exist.
+    #[clap(long, requires = "formats", conflicts_with = "single_table")]
+    split_output: Option<std::path::PathBuf>,
+    /// How to show times of day, in `time-table` and elsewhere.
+    #[clap(long, value_enum, default_value_t = TimeFormat::Clock24h)]
+    time_format: TimeFormat,
+    /// What to label each trip's column with in `time-table`.
+    #[clap(long, value_enum, default_value_t = TripNameSource::ShortName)]
+    trip_name_source: TripNameSource,
+    /// Print diagnostic information, such as the routes which were slowest to merge, to stderr.
+    #[clap(short, long)]
+    verbose: bool,
+    /// After running, print a one-line JSON summary of the run to stderr. This is printed even
+    /// if the feed or the `--names` file fails to load, with `success` false.
+    #[clap(long)]
+    report_json: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+#[value(rename_all = "snake_case")]
+enum TripNameSource {
+    /// The `trip_short_name`, falling back to the trip id.
+    ShortName,
+    /// The `trip_headsign`, falling back to the trip id.
+    Headsign,
+    /// The trip id.
+    Id,
+    /// A sequential number ("Trip 1", "Trip 2", ...), in column order.
+    Sequence,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TimeFormat {
+    /// Hours, minutes, and seconds on a 24-hour clock, e.g. `07:30:00`. Times after midnight at
+    /// the end of a service day are shown past 24:00, e.g. `25:15:00`.
+    #[value(name = "24h", alias = "clock")]
+    Clock24h,
+    /// Hours and minutes on a 12-hour clock, e.g. `7:30 AM`, with seconds only if there are any.
+    /// Times after midnight at the end of a service day are marked with the number of days
+    /// later, e.g. `1:15 AM (+1)`.
+    #[value(name = "12h")]
+    Clock12h,
+    /// An ISO 8601 duration since the start of the service day, e.g. `PT7H30M`.
+    IsoDuration,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PatternSort {
+    /// By number of trips, most first, so the dominant pattern is leftmost.
+    Count,
+    /// By which stops are served, as binary numbers with the first stop as the most significant
+    /// bit.
+    Pattern,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum StopTimeOrder {
+    /// By `stop_sequence`.
+    Sequence,
+    /// By departure time, or arrival time if there is no departure time. Stop times with
+    /// neither are sorted before all the others, so this can scramble trips with untimed stops.
+    Departure,
+}
+
+impl StopTimeOrder {
+    fn sort(self, stop_times: &mut [gtfs_structures::StopTime]) {
+        match self {
+            StopTimeOrder::Sequence => stop_times.sort_by_key(|st| st.stop_sequence),
+            StopTimeOrder::Departure => {
+                stop_times.sort_by_key(|st| st.departure_time.or(st.arrival_time))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SortBy {
+    /// The usual order of route/direction pairs: by route id, or with `--group-by-agency` or
+    /// `--group-by-type`, by group and then by heading.
+    Name,
+    Radius,
+    Diameter,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum IndexFormat {
+    Csv,
+    Json,
 }
 
 #[derive(Debug, Subcommand)]
@@ -46,222 +373,2548 @@ enum Command {
     /// Produce a set of tables in markdown format, one for each route/direction pair, showing all
     /// stopping patterns on the route.
     StoppingPatterns,
+    /// Produce a set of tables in markdown format, one for each route/direction pair, showing
+    /// groups of similar stopping patterns on the route.
+    ///
+    /// Patterns are grouped together if they are linked by a chain of patterns each differing by
+    /// at most `--cluster-distance` stops. Each group is shown by its most common pattern (•),
+    /// with the stops at which patterns in the group differ marked with "?".
+    PatternClusters,
     /// Produce a list, in markdown format, listing each route/direction pair, and the radius and
-    /// diameter of that route.
+    /// diameter of that route, and the average over its stops of the distance to the furthest
+    /// stop.
     RadiusDiameter,
+    /// Produce a list, in markdown format, of all segments between consecutive stops on a trip
+    /// whose implied speed exceeds `--max-speed`, which usually indicates a wrong coordinate or
+    /// time in the feed.
+    ///
+    /// Segments with missing coordinates or times, or with no time elapsed, are skipped.
+    SpeedCheck,
+    /// Produce a summary, in markdown format, listing each route/direction pair, and all stops
+    /// served by trips on that route, in order, checking off stops which are exact timepoints on
+    /// at least one trip.
+    ///
+    /// Stops which are not checked off only have approximate (or interpolated) times.
+    Timepoints,
+    /// Produce a list, in markdown format, of routes with problematic names: routes with neither
+    /// a short nor a long name (whose headings fall back to the raw `route_id`), and routes where
+    /// most trips share a `trip_short_name` prefix which doesn't match the route's short name.
+    NameCheck,
+    /// Run data-quality checks on the feed, and produce a report, in markdown format, of any
+    /// problems found.
+    ///
+    /// This includes the share of each route/direction's stop times with no times, which are
+    /// interpolated; if it is high, `time-table` shows few of the times riders will see.
+    ///
+    /// Route colors are checked before the feed is loaded, since a malformed color prevents it
+    /// from loading; colors with an obvious fix are shown normalized.
+    Validate,
+    /// Produce a table, in markdown format, listing each route/direction pair and a signature
+    /// hashed from its stopping patterns, for detecting which routes changed between two
+    /// versions of a feed.
+    ///
+    /// The signature depends only on the set of distinct stop id sequences served by trips on
+    /// the route, so it is stable across runs and unaffected by trip ids or times.
+    Signatures,
+    /// Produce a nested list, in markdown format, of each station and its child stops (as given
+    /// by `parent_station`), along with the routes serving each stop.
+    ///
+    /// Served stops which don't belong to a station are listed on their own.
+    Stations,
+    /// Produce a set of tables in markdown format, one for each route/direction pair, listing the
+    /// stops where trips end, and how many trips end at each.
+    BranchSummary,
+    /// Produce a table, in markdown format, listing each route/direction pair, and the compass
+    /// bearing from its first stop to its last stop, in degrees and as a compass direction.
+    ///
+    /// This is useful for checking that the trips in each direction head the same way.
+    /// Route/direction pairs whose first or last stop has no coordinates are skipped.
+    Bearings,
+    /// Produce a list, in markdown format, listing each route/direction pair, and the area (in
+    /// km²) within `--walk-distance` of any of its stops.
+    Coverage,
+    /// Produce a table, in markdown format, of the bounding box of each route/direction pair's
+    /// stops, and the length (in km) of its diagonal.
+    ///
+    /// Route/direction pairs with no stop coordinates are skipped.
+    Extents,
+    /// Produce a table, in markdown format, of the stops served by more than one route, with the
+    /// routes serving each, ordered by the number of routes, most first.
+    Transfers,
+    /// Produce a table, in markdown format, of pairs of routes with stops within
+    /// `--walk-distance` of each other, with the closest such pair of stops and the distance
+    /// between them (in meters).
+    ///
+    /// This finds potential transfers even between routes which don't share a stop. Direction
+    /// is ignored, and stops without coordinates are skipped.
+    NearbyTransfers,
+    /// Produce a table, in markdown format, of the transfers defined in the feed's
+    /// `transfers.txt`, with their type and minimum transfer time, and the routes serving the
+    /// stops at each end.
+    ///
+    /// Transfers between stops not served by any selected trip are omitted.
+    TransferRules,
+    /// Produce a table, in markdown format, listing the number of trips on each route on
+    /// weekdays, Saturdays, and Sundays.
+    ///
+    /// A trip counts towards a day type if its service runs on any day of that type, so trips
+    /// whose service runs on several day types are counted in each.
+    ServiceLevels,
+    /// Produce a table, in markdown format, listing the days of the week each route runs on, and
+    /// the number of dates on which its service is added or removed in `calendar_dates.txt`.
+    ///
+    /// A route runs on a day of the week if the service of any of its trips does.
+    ServiceCalendar,
+    /// Produce a table, in markdown format, listing each route/direction pair, and the minimum,
+    /// median, mean, and maximum headway, in seconds, between consecutive trips.
+    ///
+    /// Headways are measured at the first stop served by every trip on the route/direction.
+    /// Trips on all days are included, so this is most useful combined with `--weekday`.
+    Headways,
+    /// Produce a table, in markdown format, listing each route/direction pair, and the
+    /// distribution of the number of stops per trip, which reveals short-turn trips.
+    StopCounts,
+    /// Produce a table, in markdown format, of route/direction pairs whose merged stop list starts
+    /// or ends at a different stop than most of its trips do, which usually means short-turn or
+    /// partial trips are distorting the merged order.
+    ///
+    /// If so, `--drop-incomplete-trips` or `--order-by-shape` may give a better order.
+    TerminiCheck,
+    /// Produce a set of tables in markdown format, one for each of several metrics, ranking the
+    /// top `--top` route/direction pairs by that metric: number of stops, length of the longest
+    /// trip (in km), number of trips, and diameter (in km).
+    Leaderboard,
+    /// Produce a CSV file with one row per route/direction pair, and columns for various metrics:
+    /// the number of stops and trips, the length of the longest trip, the radius and diameter (all
+    /// in meters), the first and last departures from the start of a trip, and the service span,
+    /// from the first departure to the last arrival at the end of a trip. The departures follow
+    /// `--time-format`, but the span is always shown as `HH:MM:SS`.
+    ///
+    /// Metrics which can't be computed, due to missing coordinates or times, are left empty.
+    Metrics,
+    /// Produce a diff, in markdown format, of each route/direction pair's merged stop list
+    /// between this feed and another version of it, with `-` marking stops only in this feed
+    /// and `+` marking stops only in the other. Reordered stops show up as a removal and an
+    /// addition.
+    ///
+    /// Only route/direction pairs which differ are listed. Stops are compared by id, and the
+    /// same filters are applied to both feeds.
+    Diff {
+        /// The path or http(s) URL of the other feed.
+        other: String,
+    },
+    /// Write a GeoJSON file for each route/direction pair, with a point for each stop, and a line
+    /// connecting the stops in merged order.
+    ///
+    /// Files are named by route id and direction, e.g. `R1_Inbound.geojson`. Stops without
+    /// coordinates are left out.
+    GeoJson {
+        /// The directory to write the files to. It is created if it doesn't exist.
+        #[clap(long)]
+        out_dir: std::path::PathBuf,
+        /// Draw each route/direction pair's line along its shape from `shapes.txt`, rather than
+        /// straight from stop to stop.
+        ///
+        /// Trips on the same route/direction may use different shapes, in which case the longest
+        /// is used. Route/direction pairs without shapes fall back to connecting their stops.
+        #[clap(long)]
+        include_shapes: bool,
+    },
+    /// Write a KML file with a folder for each route, containing a line in the route's color
+    /// along the longest of its trips' shapes, and a point for each of its stops.
+    ///
+    /// Routes without shapes are drawn through their stops in merged order, taking the
+    /// direction with the most stops. Stops without coordinates are left out.
+    Kml {
+        /// The file to write.
+        #[clap(long)]
+        out: std::path::PathBuf,
+    },
+    /// Produce a table, in markdown format, listing the stops and scheduled times of a single
+    /// trip, in order.
+    TripSchedule {
+        /// The `trip_id` of the trip.
+        #[clap(long)]
+        trip: String,
+    },
+}
+
+impl Command {
+    /// Whether the command prints its output in `--format`, rather than always in markdown (or
+    /// its own fixed format).
+    fn supports_format(&self) -> bool {
+        matches!(
+            self,
+            Command::RouteSummary
+                | Command::TimeTable
+                | Command::StoppingPatterns
+                | Command::PatternClusters
+                | Command::RadiusDiameter
+                | Command::ServiceLevels
+                | Command::ServiceCalendar
+        )
+    }
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    pretty_env_logger::init();
-
-    let mut gtfs = Gtfs::new(&args.source)?;
-    log_gtfs_info(&args.source, &gtfs);
-    if let Some(route_ids) = &args.route {
-        let route_ids = route_ids.iter().collect::<HashSet<_>>();
-        gtfs.routes.retain(|id, _| route_ids.contains(id));
+    let mut args = Args::parse();
+    report::init_logger();
+    let mut report = report::Report::start();
+    let result = load_name_overrides(&mut args).and_then(|()| run(&args, &mut report));
+    if args.report_json {
+        report.emit(result.is_ok());
+    }
+    result
+}
+
+/// Loads the `--names` file into `args.name_overrides`, if one was given.
+fn load_name_overrides(args: &mut Args) -> anyhow::Result<()> {
+    if let Some(path) = &args.names {
+        args.name_overrides = types::NameOverrides::load(path)?;
+    }
+    Ok(())
+}
+
+/// Loads and filters the feed, and runs the selected command, recording the size of the filtered
+/// feed in `report`.
+fn run(args: &Args, report: &mut report::Report) -> anyhow::Result<()> {
+    if args.format == table::Format::Json && !matches!(args.command, Command::RouteSummary) {
+        anyhow::bail!("--format json is only supported by route-summary");
+    }
+    if args.format != table::Format::Markdown && !args.command.supports_format() {
+        let format = args.format.to_possible_value().expect("no skipped formats");
+        anyhow::bail!(
+            "--format {} is only supported by route-summary, time-table, stopping-patterns, \
+            pattern-clusters, radius-diameter, service-levels, and service-calendar",
+            format.get_name()
+        );
+    }
+    if args.split_output.is_some() && !matches!(args.command, Command::RouteSummary) {
+        anyhow::bail!("--split-output is only supported by route-summary");
+    }
+
+    let bytes = read_source(&args.source)?;
+    let mut color_issues = Vec::new();
+    if let Command::Validate = args.command {
+        // malformed colors prevent the feed from loading, so check them first
+        color_issues = route_colors(&args.source, bytes.as_deref())?;
+    }
+
+    let mut gtfs = match load_gtfs(&args.source, bytes.as_deref()) {
+        Ok(gtfs) => gtfs,
+        Err(err) => {
+            if !color_issues.is_empty() {
+                print_route_colors(&color_issues);
+            }
+            return Err(err);
+        }
+    };
+    log_gtfs_info(&args.source, &gtfs, bytes.as_ref().map(Vec::len));
+    if args.weekday.is_some()
+        || matches!(
+            args.command,
+            Command::ServiceLevels | Command::ServiceCalendar
+        )
+    {
+        calendar::log_date_only_services(&gtfs);
+    }
+    filter_gtfs(&mut gtfs, args)?;
+
+    report.routes = gtfs.routes.len();
+    report.trips = gtfs.trips.len();
+
+    match &args.command {
+        Command::RouteSummary => route_summary(gtfs, args),
+        Command::TimeTable => time_table(gtfs, args),
+        Command::StoppingPatterns => stopping_patterns(gtfs, args),
+        Command::PatternClusters => pattern_clusters(gtfs, args),
+        Command::RadiusDiameter => radius_and_diameter(gtfs, args),
+        Command::SpeedCheck => speed_check(gtfs, args),
+        Command::Timepoints => timepoints(gtfs, args),
+        Command::NameCheck => name_check(gtfs, args),
+        Command::Validate => validate(gtfs, args, &color_issues),
+        Command::Signatures => signatures(gtfs, args),
+        Command::Stations => stations(gtfs, args),
+        Command::BranchSummary => branch_summary(gtfs, args),
+        Command::Bearings => bearings(gtfs, args),
+        Command::Coverage => coverage(gtfs, args),
+        Command::Extents => extents(gtfs, args),
+        Command::Transfers => transfers(gtfs, args),
+        Command::NearbyTransfers => nearby_transfers(gtfs, args),
+        Command::TransferRules => transfer_rules(gtfs, args),
+        Command::ServiceLevels => service_levels(gtfs, args),
+        Command::ServiceCalendar => service_calendar(gtfs, args),
+        Command::Headways => headways(gtfs, args),
+        Command::StopCounts => stop_counts(gtfs, args),
+        Command::TerminiCheck => termini_check(gtfs, args),
+        Command::Leaderboard => leaderboard(gtfs, args),
+        Command::Metrics => metrics(gtfs, args),
+        Command::Diff { other } => diff(gtfs, args, other),
+        Command::GeoJson {
+            out_dir,
+            include_shapes,
+        } => geojson(gtfs, args, out_dir, *include_shapes),
+        Command::Kml { out } => kml(gtfs, args, out),
+        Command::TripSchedule { trip } => trip_schedule(gtfs, args, trip),
+    }
+}
+
+/// Applies the trip filters selected by `args` to a feed.
+fn filter_gtfs(gtfs: &mut Gtfs, args: &Args) -> anyhow::Result<()> {
+    if !args.route.is_empty() {
+        let feed_ids = gtfs
+            .routes
+            .keys()
+            .map(|id| filter_key(id, args.ignore_case))
+            .collect::<HashSet<_>>();
+        for id in &args.route {
+            if !feed_ids.contains(&filter_key(id, args.ignore_case)) {
+                log::warn!("Route {id} is not in the feed");
+            }
+        }
+        let route_ids = args
+            .route
+            .iter()
+            .map(|id| filter_key(id, args.ignore_case))
+            .collect::<HashSet<_>>();
+        gtfs.routes
+            .retain(|id, _| route_ids.contains(&filter_key(id, args.ignore_case)));
+        if gtfs.routes.is_empty() {
+            anyhow::bail!("None of the routes {:?} are in the feed", args.route);
+        }
+        if args.ignore_case {
+            log::info!(
+                "Matched routes: {:?}",
+                gtfs.routes.keys().collect::<Vec<_>>()
+            );
+        }
         gtfs.trips
-            .retain(|_, trip| route_ids.contains(&trip.route_id));
+            .retain(|_, trip| gtfs.routes.contains_key(&trip.route_id));
     }
     if let Some(agency_ids) = &args.agency {
-        let agency_ids = agency_ids.iter().collect::<HashSet<_>>();
+        let agency_ids = agency_ids
+            .iter()
+            .map(|id| filter_key(id, args.ignore_case))
+            .collect::<HashSet<_>>();
         gtfs.routes.retain(|_, route| {
             route
                 .agency_id
                 .as_ref()
-                .map(|id| agency_ids.contains(id))
+                .map(|id| agency_ids.contains(&filter_key(id, args.ignore_case)))
                 .unwrap_or(false)
         });
+        if args.ignore_case {
+            let matched = gtfs
+                .routes
+                .values()
+                .filter_map(|route| route.agency_id.as_ref())
+                .collect::<HashSet<_>>();
+            log::info!("Matched agencies: {matched:?}");
+        }
         gtfs.trips
             .retain(|_, trip| gtfs.routes.contains_key(&trip.route_id));
     }
+    if let Some(weekday) = args.weekday {
+        let services = gtfs
+            .trips
+            .values()
+            .map(|trip| trip.service_id.as_str())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|id| calendar::runs_on_weekday(gtfs, id, weekday).unwrap_or(false))
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        gtfs.trips
+            .retain(|_, trip| services.contains(&trip.service_id));
+        if gtfs.trips.is_empty() {
+            log::warn!("No trips run on {weekday}");
+        }
+    }
+    if args.start_date.is_some() || args.end_date.is_some() {
+        let start = args.start_date.unwrap_or(chrono::NaiveDate::MIN);
+        let end = args.end_date.unwrap_or(chrono::NaiveDate::MAX);
+        let services = gtfs
+            .trips
+            .values()
+            .map(|trip| trip.service_id.as_str())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|id| calendar::active_in_range(gtfs, id, start, end))
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        gtfs.trips
+            .retain(|_, trip| services.contains(&trip.service_id));
+        if gtfs.trips.is_empty() {
+            anyhow::bail!("No trips run between {start} and {end}");
+        }
+    }
+    if let Some(date) = args.service_date {
+        let services = gtfs
+            .trips
+            .values()
+            .map(|trip| trip.service_id.as_str())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|id| {
+                calendar::runs_on_date(gtfs, id, date).unwrap_or_else(|| {
+                    log::warn!("Dropping trips with unknown service {id}");
+                    false
+                })
+            })
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        gtfs.trips
+            .retain(|_, trip| services.contains(&trip.service_id));
+        if gtfs.trips.is_empty() {
+            log::warn!("No trips run on {date}");
+        }
+    }
+    if let Some(headsign) = &args.headsign {
+        let headsign = headsign.to_lowercase();
+        gtfs.trips.retain(|_, trip| {
+            trip.trip_headsign
+                .as_ref()
+                .is_some_and(|h| h.to_lowercase().contains(&headsign))
+        });
+        if gtfs.trips.is_empty() {
+            log::warn!("No trips have a headsign containing {headsign:?}");
+        }
+    }
+    if !args.exclude_stop.is_empty() {
+        for stop_id in &args.exclude_stop {
+            if !gtfs.stops.contains_key(stop_id) {
+                log::warn!("Excluded stop {stop_id} is not in the feed");
+            }
+        }
+        let excluded = args.exclude_stop.iter().collect::<HashSet<_>>();
+        for trip in gtfs.trips.values_mut() {
+            trip.stop_times
+                .retain(|stop_time| !excluded.contains(&stop_time.stop.id));
+        }
+    }
+    for trip in gtfs.trips.values_mut() {
+        args.order_by.sort(&mut trip.stop_times);
+    }
+    if args.drop_incomplete_trips {
+        drop_incomplete_trips(gtfs);
+    }
+    if args.collapse_same_stop_name {
+        collapse_same_stop_names(gtfs);
+    }
+    Ok(())
+}
 
-    match args.command {
-        Command::RouteSummary => route_summary(gtfs, &args),
-        Command::TimeTable => time_table(gtfs, &args),
-        Command::StoppingPatterns => stopping_patterns(gtfs, &args),
-        Command::RadiusDiameter => radius_and_diameter(gtfs, &args),
+/// Normalizes an id for comparison with the ids given to `--agency` and `--route`.
+fn filter_key(id: &str, ignore_case: bool) -> std::borrow::Cow<'_, str> {
+    if ignore_case {
+        id.to_lowercase().into()
+    } else {
+        id.into()
     }
 }
 
-fn route_summary(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
-    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+fn drop_incomplete_trips(gtfs: &mut Gtfs) {
+    use std::sync::Arc;
 
-    for (route, stops) in stops_by_route.map {
-        println!("## {}", route.format(args.use_short_name, &gtfs.routes));
-        for stop in stops {
-            println!("- {}", stop.name);
+    let before = gtfs.trips.len();
+    let stops = &gtfs.stops;
+    gtfs.trips.retain(|_, trip| {
+        trip.stop_times.len() >= 2
+            && trip.stop_times.iter().all(|st| {
+                stops
+                    .get(&st.stop.id)
+                    .is_some_and(|stop| Arc::ptr_eq(stop, &st.stop))
+            })
+    });
+    let dropped = before - gtfs.trips.len();
+    if dropped > 0 {
+        log::warn!("Dropped {dropped} incomplete trips");
+    }
+}
+
+/// Replaces each trip's stops with a single stop for each group of stops with the same name and
+/// location, rounded to about 10 m, so that they are merged as one. The stop with the lowest id
+/// in each group stands in for the rest.
+fn collapse_same_stop_names(gtfs: &mut Gtfs) {
+    use std::collections::HashMap;
+
+    use itertools::Itertools;
+
+    let key = |stop: &gtfs_structures::Stop| {
+        let round = |coord: Option<f64>| coord.map(|coord| (coord * 1e4).round() as i64);
+        (
+            stop.name.clone(),
+            round(stop.latitude),
+            round(stop.longitude),
+        )
+    };
+    let mut canonical = HashMap::new();
+    for stop in gtfs.stops.values().sorted_by(|a, b| a.id.cmp(&b.id)) {
+        canonical.entry(key(stop)).or_insert_with(|| stop.clone());
+    }
+    let mut collapsed = HashSet::new();
+    for trip in gtfs.trips.values_mut() {
+        for stop_time in &mut trip.stop_times {
+            // stops matching none of the feed's stops, e.g. missing from it, are left alone
+            let Some(stop) = canonical.get(&key(&stop_time.stop)) else {
+                continue;
+            };
+            if stop.id != stop_time.stop.id {
+                collapsed.insert(stop_time.stop.id.clone());
+                stop_time.stop = stop.clone();
+            }
         }
-        println!();
     }
-    Ok(())
+    if !collapsed.is_empty() {
+        log::info!(
+            "Collapsed {} stops into stops with the same name",
+            collapsed.len()
+        );
+    }
 }
 
-fn time_table(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
-    use std::{collections::BTreeMap, sync::Arc};
+fn route_summary(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::{
+        cmp::Reverse,
+        collections::{BTreeMap, HashMap},
+    };
 
-    use crate::table::{Align, Table};
+    use itertools::Itertools;
 
-    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+    use crate::multimap::MultiMap;
 
-    let mut tables = BTreeMap::new();
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let mut stops_by_route = MultiMap::new();
+    let mut depths = BTreeMap::<_, HashMap<_, _>>::new();
+    for (route, stops) in merge::merge_routes_with_depths(&trips_by_route, args)? {
+        if args.show_depth {
+            let route_depths = depths.entry(route.clone()).or_default();
+            for (stop, depth) in &stops {
+                route_depths.insert(stop.id.clone(), *depth);
+            }
+        }
+        stops_by_route.insert_bulk(route, stops.into_iter().map(|(stop, _)| stop).collect());
+    }
+    if args.order_by_shape {
+        shape::order_stops_by_shape(&mut stops_by_route, &gtfs, args);
+    }
 
-    for trip in gtfs.trips.values() {
-        let route_dir = types::RouteDir::from_trip(trip, args);
-        let stops = stops_by_route
-            .map
-            .get(&route_dir)
-            .expect("missing route/dir");
-        let table = tables
-            .entry(route_dir)
-            .or_insert_with(|| Table::new(stops.clone()));
-        let column = table.add_column(
-            trip.trip_short_name
-                .clone()
-                .unwrap_or_else(|| trip.id.clone()),
-            None,
-        );
+    let mut coverage = BTreeMap::<_, HashMap<_, usize>>::new();
+    if args.sort_by_coverage {
+        for (route, trips) in &trips_by_route {
+            let counts = coverage.entry(route.clone()).or_default();
+            for trip in trips {
+                for stop_id in trip.stop_times.iter().map(|st| &st.stop.id).unique() {
+                    *counts.entry(stop_id.as_str()).or_default() += 1;
+                }
+            }
+        }
+        for (route, stops) in &mut stops_by_route {
+            let counts = &coverage[route];
+            stops.sort_by_key(|stop| Reverse(counts.get(stop.id.as_str()).copied().unwrap_or(0)));
+        }
+    }
 
-        // step thru `stop.times` one at a time. since they are already sorted, we can linearly
-        // search thru `stops` for a match.
-        let mut stops = stops.iter().zip(column.iter_mut());
-        for stop_time in &trip.stop_times {
-            if let Some((_, cell)) = stops.find(|(stop, _)| Arc::ptr_eq(stop, &stop_time.stop)) {
-                *cell = stop_time.arrival_time.or(stop_time.departure_time);
-            } else {
-                log::error!("couldn't find stop {}", stop_time.stop);
-                break;
+    let route_json = |route: &types::RouteDir, stops: &[std::sync::Arc<gtfs_structures::Stop>]| {
+        let stops = stops
+            .iter()
+            .map(|stop| {
+                serde_json::json!({
+                    "id": stop.id,
+                    "name": types::format_stop(stop, args),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "route": route.format(args, &gtfs.routes),
+            "direction": format!("{:?}", route.direction),
+            "stops": stops,
+        })
+    };
+    let stop_lines = |route: &types::RouteDir, stops: &[std::sync::Arc<gtfs_structures::Stop>]| {
+        let last = stops.len().saturating_sub(1);
+        let mut cumulative = 0.0;
+        let mut previous_point = None;
+        let mut lines = Vec::with_capacity(stops.len());
+        for (i, stop) in stops.iter().enumerate() {
+            let mut line = types::format_stop(stop, args).into_owned();
+            if let Some(counts) = coverage.get(route) {
+                let trips = counts.get(stop.id.as_str()).copied().unwrap_or(0);
+                line += &format!(" ({trips} trips)");
+            }
+            if let Some(depth) = depths.get(route).and_then(|depths| depths.get(&stop.id)) {
+                line += &format!(" [depth {depth}]");
+            }
+            if args.show_distance {
+                match radius::stop_point(stop) {
+                    Some(point) => {
+                        if let Some(previous) = &previous_point {
+                            cumulative += radius::distance(previous, &point, args.metric);
+                        }
+                        previous_point = Some(point);
+                        line += &format!(" ({cumulative:.1} km)");
+                    }
+                    None => line += &format!(" ({cumulative:.1} km, no location)"),
+                }
+            }
+            if args.mark_termini {
+                if i == 0 {
+                    line += " (start)";
+                }
+                if i == last {
+                    line += " (end)";
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    };
+
+    if let Some(out_dir) = &args.split_output {
+        std::fs::create_dir_all(out_dir)?;
+        for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+            let stem = output::file_stem(&route);
+            for &format in &args.formats {
+                let contents = match format {
+                    table::Format::Json => {
+                        serde_json::to_string_pretty(&route_json(&route, &stops))? + "\n"
+                    }
+                    _ => format!(
+                        "{}\n{}",
+                        output::heading(2, &route.format(args, &gtfs.routes), format),
+                        output::list(stop_lines(&route, &stops), format)
+                    ),
+                };
+                let path = out_dir.join(format!("{stem}.{}", format.extension()));
+                std::fs::write(&path, contents)?;
+                log::info!("Wrote {}", path.display());
             }
         }
+        return Ok(());
     }
 
-    for (route, table) in tables {
-        println!("## {}", route.format(args.use_short_name, &gtfs.routes));
-        println!();
+    if args.format == table::Format::Json {
+        let routes = output::sort_sections(stops_by_route, &gtfs, args)
+            .into_iter()
+            .map(|(route, stops)| route_json(&route, &stops))
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&routes)?);
+        return Ok(());
+    }
 
+    if args.single_table {
+        use crate::table::{Align, Table};
+
+        let mut stops = Vec::new();
+        let mut routes = Vec::new();
+        for (route, route_stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+            let route = route.format(args, &gtfs.routes);
+            routes.resize(routes.len() + route_stops.len(), route);
+            stops.extend(route_stops);
+        }
+        let mut table = Table::new(stops);
+        table.push_column("Route", routes)?;
         println!(
             "{}",
-            table.formatter(
-                |trip_name| trip_name,
-                |stop| &stop.name,
-                |time| format_time_optional(*time),
-                Align::Right,
+            table.formatter_with(
+                args.format,
+                |header| header,
+                |stop| types::format_stop(stop, args),
+                |route| route,
+                Align::Left
             )
         );
+        return Ok(());
     }
-    println!();
 
-    Ok(())
+    output::print_sections(stops_by_route, &gtfs, args, |route, stops| {
+        if stops.is_empty() && args.note_empty {
+            output::print_note(output::EMPTY_NOTE, args.format);
+        }
+        output::print_list(stop_lines(route, &stops), args.format);
+        println!();
+        Ok(())
+    })
 }
 
-fn stopping_patterns(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
-    use std::{collections::BTreeMap, sync::Arc};
+fn time_table(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
 
-    use crate::{
-        bitvec::BitVec,
-        table::{Align, Table},
-    };
+    use crate::table::{Align, Aligns, Table};
 
-    let mut stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let mut stops_by_route = merge::merge_routes(&trips_by_route, args)?;
+    if args.order_by_shape {
+        shape::order_stops_by_shape(&mut stops_by_route, &gtfs, args);
+    }
 
-    let mut patterns_by_route = BTreeMap::new();
+    let first_departure = |trip: &gtfs_structures::Trip| {
+        let first = trip.stop_times.first()?;
+        first.departure_time.or(first.arrival_time)
+    };
+    const NOON: u32 = 12 * 60 * 60;
+    // route/direction pairs whose times can be shown relative to the start of each trip
+    let mut relative = BTreeMap::new();
+    let mut tables = BTreeMap::new();
 
-    for trip in gtfs.trips.values() {
-        let route_dir = types::RouteDir::from_trip(trip, args);
+    for (route_dir, trips) in trips_by_route {
+        let is_relative = args.relative_times
+            && trips.iter().fold(true, |all, trip| {
+                let has_start = first_departure(trip).is_some();
+                if !has_start {
+                    log::warn!(
+                        "trip {} has no first departure; showing absolute times for its route",
+                        trip.id
+                    );
+                }
+                all && has_start
+            });
         let stops = stops_by_route
-            .map
-            .get(&route_dir)
+            .remove(&route_dir)
             .expect("missing route/dir");
+        // the tables for the route/direction, labeled if folded into AM and PM halves
+        let mut halves = if args.fold {
+            vec![
+                (Some("AM"), Table::new(stops.clone())),
+                (Some("PM"), Table::new(stops)),
+            ]
+        } else {
+            vec![(None, Table::new(stops))]
+        };
 
-        let patterns = patterns_by_route
-            .entry(route_dir)
-            .or_insert_with(BTreeMap::new);
-        let mut pattern = BitVec::with_size(stops.len());
+        for trip in trips {
+            let is_pm = args.fold && first_departure(trip).is_some_and(|time| time >= NOON);
+            let (_, table) = &mut halves[usize::from(is_pm)];
+            let offset = if is_relative {
+                first_departure(trip).unwrap_or(0)
+            } else {
+                0
+            };
+            let trip_name = match args.trip_name_source {
+                TripNameSource::ShortName => trip.trip_short_name.clone(),
+                TripNameSource::Headsign => trip.trip_headsign.clone(),
+                // relabeled below, once all the columns are in place
+                TripNameSource::Id | TripNameSource::Sequence => None,
+            };
+            let mut column = vec![None; table.row_headers().len()];
 
-        // step thru `stop.times` one at a time. since they are already sorted, we can linearly
-        // search thru `stops` for a match.
-        let mut stops = stops.iter().enumerate();
-        for stop_time in &trip.stop_times {
-            if let Some((i, _)) = stops.find(|(_, stop)| Arc::ptr_eq(stop, &stop_time.stop)) {
-                pattern.set(i);
+            // step thru `stop.times` one at a time. since they are already sorted, we can
+            // linearly search thru `stops` for a match.
+            let mut stops = table.row_headers().iter().zip(column.iter_mut());
+            for stop_time in &trip.stop_times {
+                if let Some((_, cell)) = stops
+                    .find(|(stop, _)| types::stops_equal(stop, &stop_time.stop, args.match_by_id))
+                {
+                    *cell = stop_time
+                        .arrival_time
+                        .or(stop_time.departure_time)
+                        .map(|time| time.saturating_sub(offset));
+                } else {
+                    log::error!("couldn't find stop {}", stop_time.stop);
+                    break;
+                }
             }
+            table.push_column(trip_name.unwrap_or_else(|| trip.id.clone()), column)?;
         }
-        *patterns.entry(pattern).or_insert(0) += 1;
-    }
 
-    for (route_dir, patterns) in patterns_by_route {
-        let stops = stops_by_route
-            .map
-            .remove(&route_dir)
-            .expect("missing route/dir");
-        let mut table = Table::new(stops);
-        for (pattern, count) in patterns {
-            table.push_column(count, pattern.to_vec())?;
+        // an empty half isn't worth a heading, but if both are empty, keep one, unlabeled, as in
+        // the unfolded output, for `--note-empty`
+        if args.fold {
+            if halves.iter().all(|(_, table)| table.is_empty()) {
+                halves.truncate(1);
+                halves[0].0 = None;
+            } else {
+                halves.retain(|(_, table)| !table.is_empty());
+            }
         }
-        println!("## {}", route_dir.format(args.use_short_name, &gtfs.routes));
-        println!();
+        relative.insert(route_dir.clone(), is_relative);
+        tables.insert(route_dir, halves);
+    }
 
-        println!(
-            "{}",
-            table.formatter(
-                |count| count,
-                |stop| &stop.name,
-                |&does_stop| if does_stop { "•" } else { "" },
-                Align::Center,
-            )
-        );
+    if let TripNameSource::Sequence = args.trip_name_source {
+        for halves in tables.values_mut() {
+            let headers = halves
+                .iter_mut()
+                .flat_map(|(_, table)| table.col_headers_mut());
+            for (i, header) in headers.enumerate() {
+                *header = format!("Trip {}", i + 1);
+            }
+        }
     }
+
+    output::print_sections(tables, &gtfs, args, |route_dir, halves| {
+        let relative = relative.get(route_dir).copied().unwrap_or(false);
+        for (label, table) in halves {
+            if let Some(label) = label {
+                println!();
+                output::print_heading(3, label, args.format);
+            }
+            println!();
+            if table.is_empty() && args.note_empty {
+                output::print_note(output::EMPTY_NOTE, args.format);
+                continue;
+            }
+
+            let format_time = |time: &Option<u32>| match time {
+                Some(time) if relative => format!("{}", (time + 30) / 60).into(),
+                _ => format_time_optional(*time, args.time_format),
+            };
+            let aligns = Aligns::new(Align::Right).with_row_headers(Align::Left);
+            if args.transpose {
+                let table = table.transpose();
+                println!(
+                    "{}",
+                    table.formatter_with(
+                        args.format,
+                        |stop| types::format_stop(stop, args),
+                        |trip_name| trip_name,
+                        format_time,
+                        aligns,
+                    )
+                );
+            } else {
+                println!(
+                    "{}",
+                    table.formatter_with(
+                        args.format,
+                        |trip_name| trip_name,
+                        |stop| types::format_stop(stop, args),
+                        format_time,
+                        aligns,
+                    )
+                );
+            }
+        }
+        Ok(())
+    })?;
     println!();
 
     Ok(())
 }
 
-fn radius_and_diameter(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
-    let stops_by_route = merge::stops_by_route_unsorted(gtfs.trips.values(), args)?;
+fn stopping_patterns(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use crate::{
+        bitvec::BitVec,
+        table::{Align, Table},
+    };
 
-    let rds = stops_by_route
-        .map
-        .into_iter()
-        .map(|(k, v)| {
-            let points = v
-                .into_iter()
-                .filter_map(|stop| {
-                    stop.longitude
-                        .and_then(|long| stop.latitude.map(|lat| geo::Point::new(long, lat)))
-                })
-                .collect::<Vec<_>>();
-            let r_d = radius::radius_and_diameter(&points);
-            (k, r_d)
-        })
-        .collect::<Vec<_>>();
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let mut stops_by_route = merge::merge_routes(&trips_by_route, args)?;
+    let patterns_by_route = patterns_by_route(&trips_by_route, &stops_by_route, args);
 
-    println!("Route | radius | diameter");
-    println!("--- | --- | ---");
-    for (route, (radius, diameter)) in rds {
-        println!(
-            "{} | {radius:.3} | {diameter:.3}",
-            route.format(args.use_short_name, &gtfs.routes)
-        );
+    if let Some(format) = args.pattern_indices {
+        return print_pattern_indices(format, patterns_by_route, &stops_by_route, &gtfs, args);
     }
 
-    Ok(())
-}
-
-fn format_time_optional(time: Option<u32>) -> std::borrow::Cow<'static, str> {
-    use std::borrow::Cow;
-
-    if let Some(time) = time {
-        let hrs = time / (60 * 60);
-        let mins = (time / 60) % 60;
-        let secs = time % 60;
-        Cow::Owned(format!("{:02}:{:02}:{:02}", hrs, mins, secs))
-    } else {
-        Cow::Borrowed("")
+    output::print_sections(patterns_by_route, &gtfs, args, |route_dir, patterns| {
+        let stops = stops_by_route.remove(route_dir).expect("missing route/dir");
+        let (mut patterns, omitted): (Vec<_>, Vec<_>) = patterns
+            .into_iter()
+            .partition(|(_, count)| *count >= args.min_trips);
+        if let PatternSort::Count = args.pattern_sort {
+            // stable, so ties stay in pattern order
+            patterns.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        }
+        // stops served by some, but not all, patterns
+        let shown = || patterns.iter().map(|(pattern, _)| pattern);
+        let branch = BitVec::union_all(shown())
+            .zip(BitVec::intersection_all(shown()))
+            .filter(|_| args.mark_branch_stops)
+            .map(|(served, all)| served.difference(&all));
+        let is_branch = |i| branch.as_ref().is_some_and(|branch| branch.get(i));
+        // row headers are `None` for the summary row
+        let stops = stops
+            .into_iter()
+            .enumerate()
+            .map(|(i, stop)| Some((stop, is_branch(i))))
+            .collect();
+        let format_stop = |stop: &Option<(std::sync::Arc<gtfs_structures::Stop>, bool)>| {
+            let Some((stop, is_branch)) = stop else {
+                return "Stops served".to_owned();
+            };
+            let name = types::format_stop(stop, args);
+            if *is_branch {
+                format!("{name} (branch)")
+            } else {
+                name.into_owned()
+            }
+        };
+        let mut table = Table::new(stops);
+        for (pattern, count) in patterns {
+            let cells = pattern
+                .to_vec()
+                .into_iter()
+                .map(PatternCell::Stop)
+                .collect();
+            table.push_column(count, cells)?;
+        }
+        let table = table.with_summary_row(None, |cells| {
+            PatternCell::Count(
+                cells
+                    .iter()
+                    .filter(|cell| matches!(cell, PatternCell::Stop(true)))
+                    .count(),
+            )
+        });
+        println!();
+        if table.is_empty() && args.note_empty {
+            output::print_note(output::EMPTY_NOTE, args.format);
+            return Ok(());
+        }
+
+        let format_cell = |cell: &PatternCell| match cell {
+            PatternCell::Stop(true) => "•".to_owned(),
+            PatternCell::Stop(false) => String::new(),
+            PatternCell::Count(count) => count.to_string(),
+        };
+        if args.transpose {
+            let table = table.transpose();
+            println!(
+                "{}",
+                table.formatter_with(
+                    args.format,
+                    format_stop,
+                    |count| count,
+                    format_cell,
+                    Align::Center,
+                )
+            );
+        } else {
+            println!(
+                "{}",
+                table.formatter_with(
+                    args.format,
+                    |count| count,
+                    format_stop,
+                    format_cell,
+                    Align::Center,
+                )
+            );
+        }
+        if !omitted.is_empty() {
+            let note = format!(
+                "{} patterns ({} trips) with fewer than {} trips omitted.",
+                omitted.len(),
+                omitted.iter().map(|(_, count)| count).sum::<usize>(),
+                args.min_trips
+            );
+            output::print_note(&note, args.format);
+        }
+        Ok(())
+    })?;
+    println!();
+
+    Ok(())
+}
+
+/// A cell of a stopping pattern table: whether the pattern serves the stop, or, in the summary
+/// row, how many stops the pattern serves.
+enum PatternCell {
+    Stop(bool),
+    Count(usize),
+}
+
+/// Counts the trips with each stopping pattern on each route/direction pair, where a pattern
+/// marks which of the route/direction's stops a trip serves.
+fn patterns_by_route(
+    trips_by_route: &multimap::MultiMap<types::RouteDir, &gtfs_structures::Trip>,
+    stops_by_route: &multimap::MultiMap<types::RouteDir, std::sync::Arc<gtfs_structures::Stop>>,
+    args: &Args,
+) -> std::collections::BTreeMap<types::RouteDir, std::collections::BTreeMap<bitvec::BitVec, usize>>
+{
+    use std::collections::BTreeMap;
+
+    use crate::bitvec::BitVec;
+
+    let mut patterns_by_route = BTreeMap::new();
+
+    for (route_dir, trips) in trips_by_route {
+        let stops = stops_by_route.get(route_dir).expect("missing route/dir");
+        let mut patterns = BTreeMap::new();
+
+        for trip in trips {
+            let mut pattern = BitVec::with_size(stops.len());
+
+            // step thru `stop.times` one at a time. since they are already sorted, we can
+            // linearly search thru `stops` for a match.
+            let mut stops = stops.iter().enumerate();
+            for stop_time in &trip.stop_times {
+                if let Some((i, _)) = stops
+                    .find(|(_, stop)| types::stops_equal(stop, &stop_time.stop, args.match_by_id))
+                {
+                    pattern.set(i);
+                }
+            }
+            *patterns.entry(pattern).or_insert(0) += 1;
+        }
+
+        patterns_by_route.insert(route_dir.clone(), patterns);
+    }
+
+    patterns_by_route
+}
+
+fn print_pattern_indices(
+    format: IndexFormat,
+    patterns_by_route: std::collections::BTreeMap<
+        types::RouteDir,
+        std::collections::BTreeMap<bitvec::BitVec, usize>,
+    >,
+    stops_by_route: &multimap::MultiMap<types::RouteDir, std::sync::Arc<gtfs_structures::Stop>>,
+    gtfs: &Gtfs,
+    args: &Args,
+) -> anyhow::Result<()> {
+    use itertools::Itertools;
+
+    use crate::output::csv_field;
+
+    let sections = output::sort_sections(patterns_by_route, gtfs, args);
+    let stop_ids = |route_dir: &types::RouteDir| {
+        stops_by_route
+            .get(route_dir)
+            .expect("missing route/dir")
+            .iter()
+            .map(|stop| stop.id.as_str())
+    };
+
+    match format {
+        IndexFormat::Csv => {
+            println!("route_id,direction,trips,stop_indices");
+            for (route_dir, patterns) in sections {
+                let route_id = csv_field(route_dir.route_id.as_deref().unwrap_or(""));
+                let legend = stop_ids(&route_dir)
+                    .enumerate()
+                    .map(|(i, id)| format!("{i}={id}"))
+                    .join(" ");
+                println!("# {route_id} {:?}: {legend}", route_dir.direction);
+                for (pattern, count) in patterns {
+                    println!(
+                        "{route_id},{:?},{count},{}",
+                        route_dir.direction,
+                        pattern.iter_ones().join(" ")
+                    );
+                }
+            }
+        }
+        IndexFormat::Json => {
+            let routes = sections
+                .into_iter()
+                .map(|(route_dir, patterns)| {
+                    let patterns = patterns
+                        .into_iter()
+                        .map(|(pattern, count)| {
+                            serde_json::json!({
+                                "trips": count,
+                                "num_stops": pattern.count_ones(),
+                                "stops": pattern.iter_ones().collect::<Vec<_>>(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    serde_json::json!({
+                        "route_id": route_dir.route_id,
+                        "direction": format!("{:?}", route_dir.direction),
+                        "headsign": route_dir.headsign,
+                        "stop_ids": stop_ids(&route_dir).collect::<Vec<_>>(),
+                        "patterns": patterns,
+                    })
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&routes)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn pattern_clusters(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use crate::{
+        bitvec::BitVec,
+        table::{Align, Table},
+    };
+
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let mut stops_by_route = merge::merge_routes(&trips_by_route, args)?;
+    let patterns_by_route = patterns_by_route(&trips_by_route, &stops_by_route, args);
+
+    output::print_sections(patterns_by_route, &gtfs, args, |route_dir, patterns| {
+        let stops = stops_by_route.remove(route_dir).expect("missing route/dir");
+        let patterns = patterns.into_iter().collect::<Vec<_>>();
+
+        // single-linkage clustering, by merging the clusters of every pair of patterns which are
+        // close enough
+        let mut cluster_of = (0..patterns.len()).collect::<Vec<_>>();
+        for i in 0..patterns.len() {
+            for j in 0..i {
+                if patterns[i].0.hamming_distance(&patterns[j].0) <= args.cluster_distance {
+                    let (old, new) = (cluster_of[i], cluster_of[j]);
+                    for cluster in &mut cluster_of {
+                        if *cluster == old {
+                            *cluster = new;
+                        }
+                    }
+                }
+            }
+        }
+        let clusters = cluster_of
+            .iter()
+            .zip(&patterns)
+            .map(|(&cluster, pattern)| (cluster, pattern))
+            .collect::<multimap::MultiMap<_, _>>();
+
+        let mut clusters = clusters
+            .into_values()
+            .map(|members| {
+                let trips = members.iter().map(|(_, count)| count).sum::<usize>();
+                (trips, members)
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by_key(|(trips, _)| std::cmp::Reverse(*trips));
+
+        let mut table = Table::new(stops);
+        for (i, (trips, members)) in clusters.into_iter().enumerate() {
+            let representative = members
+                .iter()
+                .max_by_key(|(_, count)| count)
+                .map(|(pattern, _)| pattern.to_vec())
+                .expect("empty cluster");
+            let patterns = members.iter().map(|(pattern, _)| pattern);
+            let union = BitVec::union_all(patterns.clone()).expect("empty cluster");
+            let intersection = BitVec::intersection_all(patterns).expect("empty cluster");
+            let column = representative
+                .into_iter()
+                .zip(union.to_vec().into_iter().zip(intersection.to_vec()))
+                .map(|(stops, (some, all))| match (stops, some != all) {
+                    (true, false) => "•",
+                    (true, true) => "•?",
+                    (false, true) => "?",
+                    (false, false) => "",
+                })
+                .collect();
+            table.push_column(
+                format!(
+                    "Group {} ({trips} trips, {} patterns)",
+                    i + 1,
+                    members.len()
+                ),
+                column,
+            )?;
+        }
+        println!();
+        if table.is_empty() && args.note_empty {
+            output::print_note(output::EMPTY_NOTE, args.format);
+            return Ok(());
+        }
+
+        println!(
+            "{}",
+            table.formatter_with(
+                args.format,
+                |header| header,
+                |stop| types::format_stop(stop, args),
+                |cell| cell,
+                Align::Center,
+            )
+        );
+        Ok(())
+    })?;
+    println!();
+
+    Ok(())
+}
+
+fn radius_and_diameter(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use rayon::prelude::*;
+
+    use crate::table::{Align, Table};
+
+    let stops_by_route = merge::stops_by_route_unsorted(gtfs.trips.values(), args)?;
+
+    let points_by_route = output::sort_sections(stops_by_route, &gtfs, args)
+        .into_iter()
+        .map(|(k, v)| {
+            let points = v
+                .into_iter()
+                .filter_map(|stop| radius::stop_point(&stop))
+                .collect::<Vec<_>>();
+            (k.format(args, &gtfs.routes), points)
+        })
+        .collect::<Vec<_>>();
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = args.jobs {
+        pool = pool.num_threads(jobs);
+    }
+    let mut rds = pool.build()?.install(|| {
+        points_by_route
+            .into_par_iter()
+            .map(|(route, points)| (route, radius::radius_diameter_average(&points, args.metric)))
+            .collect::<Vec<_>>()
+    });
+    match args.sort_by {
+        // already in section order
+        SortBy::Name => {}
+        SortBy::Radius => rds.sort_by(|(_, (a, _, _)), (_, (b, _, _))| b.total_cmp(a)),
+        SortBy::Diameter => rds.sort_by(|(_, (_, a, _)), (_, (_, b, _))| b.total_cmp(a)),
+    }
+
+    let (routes, stats): (Vec<_>, Vec<_>) = rds.into_iter().unzip();
+    let mut table = Table::new(routes);
+    table.push_column("radius", stats.iter().map(|stats| stats.0).collect())?;
+    table.push_column("diameter", stats.iter().map(|stats| stats.1).collect())?;
+    table.push_column("average", stats.iter().map(|stats| stats.2).collect())?;
+    println!(
+        "{}",
+        table.formatter_with(
+            args.format,
+            |header| header,
+            |route| route,
+            |km| format!("{km:.3}"),
+            Align::Right,
+        )
+    );
+
+    Ok(())
+}
+
+fn bearings(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    println!("Route | Bearing | Direction");
+    println!("--- | --: | ---");
+    for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+        let first = stops.first().and_then(|stop| radius::stop_point(stop));
+        let last = stops.last().and_then(|stop| radius::stop_point(stop));
+        let (Some(first), Some(last)) = (first, last) else {
+            log::warn!("no coordinates for the ends of {route:?}; skipping");
+            continue;
+        };
+        let bearing = radius::bearing(&first, &last);
+        println!(
+            "{} | {bearing:.0}° | {}",
+            route.format(args, &gtfs.routes),
+            radius::cardinal(bearing)
+        );
+    }
+
+    Ok(())
+}
+
+fn coverage(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    let stops_by_route = merge::stops_by_route_unsorted(gtfs.trips.values(), args)?;
+
+    println!("Route | area (km²)");
+    println!("--- | ---");
+    for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+        let points = stops
+            .iter()
+            .filter_map(|stop| radius::stop_point(stop))
+            .collect::<Vec<_>>();
+        let area = coverage::coverage_area(&points, args.walk_distance / 1000.0);
+        println!("{} | {area:.3}", route.format(args, &gtfs.routes));
+    }
+
+    Ok(())
+}
+
+fn extents(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use geo::BoundingRect;
+
+    let stops_by_route = merge::stops_by_route_unsorted(gtfs.trips.values(), args)?;
+
+    println!("Route | Direction | Min lat | Max lat | Min lon | Max lon | Diagonal (km)");
+    println!("--- | --- | --: | --: | --: | --: | --:");
+    for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+        let points = stops
+            .iter()
+            .filter_map(|stop| radius::stop_point(stop))
+            .collect::<geo::MultiPoint>();
+        let Some(rect) = points.bounding_rect() else {
+            log::warn!("no coordinates for the stops of {route:?}; skipping");
+            continue;
+        };
+        let (min, max) = (rect.min(), rect.max());
+        let diagonal = radius::distance(&min.into(), &max.into(), args.metric);
+        println!(
+            "{} | {:?} | {:.5} | {:.5} | {:.5} | {:.5} | {diagonal:.3}",
+            route
+                .route_id
+                .as_deref()
+                .map(|route_id| types::format_route(route_id, args, &gtfs.routes))
+                .unwrap_or_default(),
+            route.direction,
+            min.y,
+            max.y,
+            min.x,
+            max.x,
+        );
+    }
+
+    Ok(())
+}
+
+fn transfers(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use itertools::Itertools;
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    // the routes serving each stop, by stop id
+    let mut routes_by_stop = BTreeMap::<_, (_, BTreeSet<_>)>::new();
+    for (route_dir, stops) in &stops_by_route {
+        let Some(route_id) = &route_dir.route_id else {
+            continue;
+        };
+        let route = types::format_route(route_id, args, &gtfs.routes);
+        for stop in stops {
+            routes_by_stop
+                .entry(stop.id.as_str())
+                .or_insert_with(|| (stop, BTreeSet::new()))
+                .1
+                .insert(route);
+        }
+    }
+
+    let rows = routes_by_stop
+        .into_values()
+        .filter(|(_, routes)| routes.len() > 1)
+        .sorted_by(|(a_stop, a_routes), (b_stop, b_routes)| {
+            b_routes
+                .len()
+                .cmp(&a_routes.len())
+                .then_with(|| (&a_stop.name, &a_stop.id).cmp(&(&b_stop.name, &b_stop.id)))
+        });
+    println!("Stop | Routes");
+    println!("--- | ---");
+    for (stop, routes) in rows {
+        println!(
+            "{} | {}",
+            types::format_stop(stop, args),
+            routes.iter().join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+fn nearby_transfers(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    let stops_by_route = transfers::stops_by_route_id(&gtfs);
+    let mut nearby =
+        transfers::nearby_stops(&stops_by_route, args.walk_distance / 1000.0, args.metric);
+    nearby.sort_by_key(|nearby| {
+        (
+            types::format_route(nearby.routes.0, args, &gtfs.routes),
+            types::format_route(nearby.routes.1, args, &gtfs.routes),
+        )
+    });
+
+    println!("Route | Stop | Route | Stop | Distance (m)");
+    println!("--- | --- | --- | --- | --:");
+    for nearby in &nearby {
+        println!(
+            "{} | {} | {} | {} | {:.0}",
+            types::format_route(nearby.routes.0, args, &gtfs.routes),
+            types::format_stop(nearby.stops.0, args),
+            types::format_route(nearby.routes.1, args, &gtfs.routes),
+            types::format_stop(nearby.stops.1, args),
+            nearby.distance * 1000.0,
+        );
+    }
+
+    Ok(())
+}
+
+fn service_levels(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::{BTreeMap, HashMap};
+
+    use chrono::Weekday;
+    use itertools::Itertools;
+
+    use crate::table::{Align, Table};
+
+    const DAY_TYPES: [(&str, &[Weekday]); 3] = [
+        (
+            "Weekday",
+            &[
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+        ),
+        ("Saturday", &[Weekday::Sat]),
+        ("Sunday", &[Weekday::Sun]),
+    ];
+
+    let mut day_types_by_service = HashMap::new();
+    let mut counts = BTreeMap::<_, [usize; DAY_TYPES.len()]>::new();
+    for trip in gtfs.trips.values() {
+        let day_types = day_types_by_service
+            .entry(&trip.service_id)
+            .or_insert_with(|| {
+                DAY_TYPES.map(|(_, days)| {
+                    days.iter().any(|&day| {
+                        calendar::runs_on_weekday(&gtfs, &trip.service_id, day).unwrap_or(false)
+                    })
+                })
+            });
+        let route_counts = counts.entry(&trip.route_id).or_default();
+        for (count, &runs) in route_counts.iter_mut().zip(day_types.iter()) {
+            if runs {
+                *count += 1;
+            }
+        }
+    }
+
+    let (routes, counts): (Vec<_>, Vec<_>) = counts
+        .into_iter()
+        .map(|(route_id, counts)| (types::format_route(route_id, args, &gtfs.routes), counts))
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .unzip();
+    let mut table = Table::new(routes);
+    for (i, (day_type, _)) in DAY_TYPES.iter().enumerate() {
+        table.push_column(*day_type, counts.iter().map(|counts| counts[i]).collect())?;
+    }
+    println!(
+        "{}",
+        table.formatter_with(
+            args.format,
+            |day_type| day_type,
+            |route| route,
+            |count| count,
+            Align::Right,
+        )
+    );
+
+    Ok(())
+}
+
+fn service_calendar(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use chrono::Weekday;
+    use itertools::Itertools;
+
+    use crate::table::{Align, Aligns, Table};
+
+    const WEEKDAYS: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    let mut services_by_route = BTreeMap::<_, HashSet<_>>::new();
+    for trip in gtfs.trips.values() {
+        services_by_route
+            .entry(&trip.route_id)
+            .or_default()
+            .insert(trip.service_id.as_str());
+    }
+
+    let (routes, days): (Vec<_>, Vec<_>) = services_by_route
+        .into_iter()
+        .map(|(route_id, services)| {
+            let weekdays = WEEKDAYS.map(|day| {
+                services.iter().any(|service_id| {
+                    calendar::runs_on_weekday(&gtfs, service_id, day).unwrap_or(false)
+                })
+            });
+            let exceptions = services
+                .iter()
+                .flat_map(|service_id| calendar::exception_dates(&gtfs, service_id))
+                .collect::<BTreeSet<_>>();
+            (
+                types::format_route(route_id, args, &gtfs.routes),
+                (weekdays, exceptions.len()),
+            )
+        })
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .unzip();
+    let mut table = Table::new(routes);
+    for (i, day) in WEEKDAYS.iter().enumerate() {
+        let runs = days
+            .iter()
+            .map(|(weekdays, _)| if weekdays[i] { "✓" } else { "" }.to_owned())
+            .collect();
+        table.push_column(day.to_string(), runs)?;
+    }
+    table.push_column(
+        "Exceptions".to_owned(),
+        days.iter()
+            .map(|(_, exceptions)| exceptions.to_string())
+            .collect(),
+    )?;
+    let mut aligns = vec![Align::Center; WEEKDAYS.len()];
+    aligns.push(Align::Right);
+    println!(
+        "{}",
+        table.formatter_with(
+            args.format,
+            |day| day,
+            |route| route,
+            |cell| cell,
+            Aligns::new(Align::Center).with_columns(aligns),
+        )
+    );
+
+    Ok(())
+}
+
+fn headways(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let stops_by_route = merge::merge_routes(&trips_by_route, args)?;
+
+    println!("Route | Min | Median | Mean | Max");
+    println!("--- | --: | --: | --: | --:");
+    for (route, trips) in output::sort_sections(trips_by_route, &gtfs, args) {
+        let stops = stops_by_route.get(&route).expect("missing route/dir");
+        let stop_time_at = |trip, stop| types::stop_time_at(trip, stop, args.match_by_id);
+        let Some(shared) = stops
+            .iter()
+            .find(|stop| trips.iter().all(|trip| stop_time_at(trip, stop).is_some()))
+        else {
+            log::warn!("no stop is served by every trip in {route:?}; skipping");
+            continue;
+        };
+
+        let mut departures = trips
+            .iter()
+            .filter_map(|trip| {
+                let stop_time = stop_time_at(trip, shared)?;
+                let departure = stop_time.departure_time.or(stop_time.arrival_time);
+                if departure.is_none() {
+                    log::warn!("trip {} has no time at {shared}; skipping", trip.id);
+                }
+                departure
+            })
+            .collect::<Vec<_>>();
+        departures.sort_unstable();
+        let mut headways = departures
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]) as usize)
+            .collect::<Vec<_>>();
+        if headways.is_empty() {
+            continue;
+        }
+        headways.sort_unstable();
+        println!(
+            "{} | {} | {} | {:.1} | {}",
+            route.format(args, &gtfs.routes),
+            headways[0],
+            median(&headways),
+            headways.iter().sum::<usize>() as f64 / headways.len() as f64,
+            headways[headways.len() - 1],
+        );
+    }
+
+    Ok(())
+}
+
+fn stop_counts(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use crate::multimap::MultiMap;
+
+    let counts_by_route = gtfs
+        .trips
+        .values()
+        .filter(|trip| !trip.stop_times.is_empty())
+        .map(|trip| {
+            (
+                types::RouteDir::from_trip(trip, args),
+                trip.stop_times.len(),
+            )
+        })
+        .collect::<MultiMap<_, _>>();
+
+    println!("Route | Trips | Min | Median | Max");
+    println!("--- | --: | --: | --: | --:");
+    for (route, mut counts) in output::sort_sections(counts_by_route, &gtfs, args) {
+        counts.sort_unstable();
+        println!(
+            "{} | {} | {} | {} | {}",
+            route.format(args, &gtfs.routes),
+            counts.len(),
+            counts[0],
+            median(&counts),
+            counts[counts.len() - 1],
+        );
+    }
+
+    Ok(())
+}
+
+fn termini_check(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::{BTreeMap, HashMap};
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    let mut termini_by_route = BTreeMap::<_, [HashMap<_, _>; 2]>::new();
+    for trip in gtfs.trips.values() {
+        let (Some(first), Some(last)) = (trip.stop_times.first(), trip.stop_times.last()) else {
+            continue;
+        };
+        let termini = termini_by_route
+            .entry(types::RouteDir::from_trip(trip, args))
+            .or_default();
+        for (counts, stop_time) in termini.iter_mut().zip([first, last]) {
+            counts
+                .entry(&stop_time.stop.id)
+                .or_insert((&stop_time.stop, 0))
+                .1 += 1;
+        }
+    }
+
+    println!("Route | End | Merged | Most common | Trips");
+    println!("--- | --- | --- | --- | --:");
+    for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+        let Some(termini) = termini_by_route.get(&route) else {
+            continue;
+        };
+        for ((end, merged), counts) in [("start", stops.first()), ("end", stops.last())]
+            .into_iter()
+            .zip(termini)
+        {
+            let Some(merged) = merged else {
+                continue;
+            };
+            let common = counts.values().max_by(|(a, a_count), (b, b_count)| {
+                a_count.cmp(b_count).then_with(|| b.id.cmp(&a.id))
+            });
+            if let Some((common, count)) = common {
+                if common.id != merged.id {
+                    println!(
+                        "{} | {end} | {} | {} | {count}",
+                        route.format(args, &gtfs.routes),
+                        types::format_stop(merged, args),
+                        types::format_stop(common, args),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn leaderboard(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
+
+    use itertools::Itertools;
+
+    struct Row {
+        route: String,
+        stops: usize,
+        length: f64,
+        trips: usize,
+        diameter: f64,
+    }
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    let mut trips = BTreeMap::<_, usize>::new();
+    let mut lengths = BTreeMap::<_, f64>::new();
+    for trip in gtfs.trips.values() {
+        let route = types::RouteDir::from_trip(trip, args);
+        *trips.entry(route.clone()).or_default() += 1;
+        let length = radius::trip_length(trip, args.metric).unwrap_or(0.0);
+        let longest = lengths.entry(route).or_default();
+        *longest = longest.max(length);
+    }
+
+    let rows = output::sort_sections(stops_by_route, &gtfs, args)
+        .into_iter()
+        .map(|(route, stops)| {
+            let points = stops
+                .iter()
+                .filter_map(|stop| radius::stop_point(stop))
+                .collect::<Vec<_>>();
+            let (_, diameter) = radius::radius_and_diameter(&points, args.metric);
+            Row {
+                stops: stops.len(),
+                length: lengths.get(&route).copied().unwrap_or(0.0),
+                trips: trips.get(&route).copied().unwrap_or(0),
+                diameter,
+                route: route.format(args, &gtfs.routes),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let print_category = |title: &str, header: &str, precision: usize, metric: fn(&Row) -> f64| {
+        // stable sort, so ties keep the usual route order
+        let ranked = rows
+            .iter()
+            .sorted_by(|a, b| metric(b).total_cmp(&metric(a)))
+            .take(args.top);
+        println!("## {title}");
+        println!();
+        println!("Rank | Route | {header}");
+        println!("--: | --- | --:");
+        for (rank, row) in ranked.enumerate() {
+            println!("{} | {} | {:.precision$}", rank + 1, row.route, metric(row));
+        }
+        println!();
+    };
+    print_category("Most stops", "Stops", 0, |row| row.stops as f64);
+    print_category("Longest trip", "Length (km)", 3, |row| row.length);
+    print_category("Most trips", "Trips", 0, |row| row.trips as f64);
+    print_category("Largest diameter", "Diameter (km)", 3, |row| row.diameter);
+
+    Ok(())
+}
+
+fn metrics(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
+
+    use crate::output::csv_field;
+
+    #[derive(Default)]
+    struct TripMetrics {
+        trips: usize,
+        length: Option<f64>,
+        first_departure: Option<u32>,
+        last_departure: Option<u32>,
+        last_arrival: Option<u32>,
+    }
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    let mut trip_metrics = BTreeMap::<_, TripMetrics>::new();
+    for trip in gtfs.trips.values() {
+        let metrics = trip_metrics
+            .entry(types::RouteDir::from_trip(trip, args))
+            .or_default();
+        metrics.trips += 1;
+        if let Some(length) = radius::trip_length(trip, args.metric) {
+            metrics.length = Some(metrics.length.map_or(length, |l| l.max(length)));
+        }
+        let departure = trip
+            .stop_times
+            .first()
+            .and_then(|st| st.departure_time.or(st.arrival_time));
+        if let Some(departure) = departure {
+            metrics.first_departure = Some(
+                metrics
+                    .first_departure
+                    .map_or(departure, |t| t.min(departure)),
+            );
+            metrics.last_departure = metrics.last_departure.max(Some(departure));
+        }
+        let arrival = trip
+            .stop_times
+            .last()
+            .and_then(|st| st.arrival_time.or(st.departure_time));
+        metrics.last_arrival = metrics.last_arrival.max(arrival);
     }
+
+    let meters = |km: f64| format!("{:.0}", km * 1000.0);
+
+    println!("route_id,direction,route,stops,trips,length_m,radius_m,diameter_m,first_departure,last_departure,service_span");
+    for (route, stops) in output::sort_sections(stops_by_route, &gtfs, args) {
+        let points = stops
+            .iter()
+            .filter_map(|stop| radius::stop_point(stop))
+            .collect::<Vec<_>>();
+        let (radius, diameter) = if points.is_empty() {
+            (String::new(), String::new())
+        } else {
+            let (radius, diameter) = radius::radius_and_diameter(&points, args.metric);
+            (meters(radius), meters(diameter))
+        };
+        let metrics = trip_metrics.remove(&route).unwrap_or_default();
+        let span = metrics
+            .first_departure
+            .zip(metrics.last_arrival)
+            .map(|(first, last)| last.saturating_sub(first));
+        println!(
+            "{},{:?},{},{},{},{},{radius},{diameter},{},{},{}",
+            csv_field(route.route_id.as_deref().unwrap_or("")),
+            route.direction,
+            csv_field(&route.format(args, &gtfs.routes)),
+            stops.len(),
+            metrics.trips,
+            metrics.length.map(meters).unwrap_or_default(),
+            format_time_optional(metrics.first_departure, args.time_format),
+            format_time_optional(metrics.last_departure, args.time_format),
+            // the span is a duration, not a time of day, so it is always `HH:MM:SS`
+            format_time_optional(span, TimeFormat::Clock24h),
+        );
+    }
+
+    Ok(())
+}
+
+fn diff(gtfs: Gtfs, args: &Args, other: &str) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
+
+    use crate::diff::Change;
+
+    let bytes = read_source(other)?;
+    let mut other_gtfs = load_gtfs(other, bytes.as_deref())?;
+    log_gtfs_info(other, &other_gtfs, bytes.as_ref().map(Vec::len));
+    filter_gtfs(&mut other_gtfs, args)?;
+
+    let mut old = merge::stops_by_route(gtfs.trips.values(), args)?;
+    let mut new = merge::stops_by_route(other_gtfs.trips.values(), args)?;
+    let route_dirs = old
+        .iter()
+        .chain(&new)
+        .map(|(route_dir, _)| route_dir.clone())
+        .collect::<std::collections::BTreeSet<_>>();
+    let mut diffs = BTreeMap::new();
+    for route_dir in route_dirs {
+        let old_stops = old.remove(&route_dir).unwrap_or_default();
+        let new_stops = new.remove(&route_dir).unwrap_or_default();
+        let changes = diff::diff_by(&old_stops, &new_stops, |a, b| a.id == b.id);
+        if changes
+            .iter()
+            .all(|change| matches!(change, Change::Same(_)))
+        {
+            continue;
+        }
+        let lines = changes
+            .into_iter()
+            .map(|change| {
+                let stop = types::format_stop(change.value(), args).into_owned();
+                (change.marker(), stop)
+            })
+            .collect::<Vec<_>>();
+        diffs.insert(route_dir, lines);
+    }
+
+    output::print_sections(diffs, &gtfs, args, |_, lines| {
+        println!();
+        println!("```diff");
+        for (marker, stop) in lines {
+            println!("{marker} {stop}");
+        }
+        println!("```");
+        Ok(())
+    })?;
+    println!();
+
+    Ok(())
+}
+
+fn geojson(
+    gtfs: Gtfs,
+    args: &Args,
+    out_dir: &std::path::Path,
+    include_shapes: bool,
+) -> anyhow::Result<()> {
+    use serde_json::json;
+
+    let trips_by_route = merge::trips_by_route(gtfs.trips.values(), args);
+    let stops_by_route = merge::merge_routes(&trips_by_route, args)?;
+
+    std::fs::create_dir_all(out_dir)?;
+    for (route, stops) in stops_by_route {
+        let points = stops
+            .iter()
+            .filter_map(|stop| Some((stop, radius::stop_point(stop)?)))
+            .collect::<Vec<_>>();
+        let mut features = points
+            .iter()
+            .map(|(stop, point)| {
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Point",
+                        "coordinates": [point.x(), point.y()],
+                    },
+                    "properties": {
+                        "name": types::format_stop(stop, args),
+                        "stop_id": stop.id,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        let shape = include_shapes
+            .then(|| {
+                let shape_ids = trips_by_route
+                    .get(&route)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|trip| trip.shape_id.as_deref());
+                shape::longest_shape(&gtfs, shape_ids, args.metric)
+            })
+            .flatten();
+        let line = shape.unwrap_or_else(|| points.iter().map(|(_, point)| *point).collect());
+        // a line needs at least two points
+        if line.0.len() >= 2 {
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": line
+                        .points()
+                        .map(|point| [point.x(), point.y()])
+                        .collect::<Vec<_>>(),
+                },
+                "properties": {
+                    "route": route.format(args, &gtfs.routes),
+                },
+            }));
+        }
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+
+        let path = out_dir.join(format!("{}.geojson", output::file_stem(&route)));
+        std::fs::write(&path, serde_json::to_string_pretty(&collection)?)?;
+        log::info!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn kml(gtfs: Gtfs, args: &Args, out: &std::path::Path) -> anyhow::Result<()> {
+    use std::{collections::BTreeMap, fmt::Write};
+
+    use itertools::Itertools;
+
+    use crate::output::html_escape;
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+    // the merged stops of each route's direction with the most stops
+    let mut longest_stops = BTreeMap::<&str, &[_]>::new();
+    for (route_dir, stops) in &stops_by_route {
+        let Some(route_id) = &route_dir.route_id else {
+            continue;
+        };
+        let longest = longest_stops.entry(route_id).or_default();
+        if stops.len() > longest.len() {
+            *longest = stops;
+        }
+    }
+
+    let mut kml = String::new();
+    writeln!(kml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(kml, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(kml, "<Document>")?;
+    let routes = longest_stops
+        .into_iter()
+        .sorted_by_key(|(route_id, _)| types::format_route(route_id, args, &gtfs.routes));
+    for (route_id, merged_stops) in routes {
+        let name = types::format_route(route_id, args, &gtfs.routes);
+        writeln!(kml, "<Folder>")?;
+        writeln!(kml, "<name>{}</name>", html_escape(name))?;
+
+        let shape_ids = gtfs
+            .trips
+            .values()
+            .filter(|trip| trip.route_id == route_id)
+            .filter_map(|trip| trip.shape_id.as_deref());
+        let line = shape::longest_shape(&gtfs, shape_ids, args.metric).unwrap_or_else(|| {
+            merged_stops
+                .iter()
+                .filter_map(|stop| radius::stop_point(stop))
+                .collect()
+        });
+        // a line needs at least two points
+        if line.0.len() >= 2 {
+            let color = gtfs.routes.get(route_id).map(|route| route.color);
+            writeln!(kml, "<Placemark>")?;
+            writeln!(kml, "<name>{}</name>", html_escape(name))?;
+            if let Some(color) = color {
+                // KML colors are alpha, blue, green, red
+                writeln!(
+                    kml,
+                    "<Style><LineStyle><color>ff{:02x}{:02x}{:02x}</color><width>3</width></LineStyle></Style>",
+                    color.b, color.g, color.r
+                )?;
+            }
+            let coordinates = line
+                .points()
+                .map(|point| format!("{},{}", point.x(), point.y()))
+                .join(" ");
+            writeln!(
+                kml,
+                "<LineString><coordinates>{coordinates}</coordinates></LineString>"
+            )?;
+            writeln!(kml, "</Placemark>")?;
+        }
+
+        let stops = stops_by_route
+            .iter()
+            .filter(|(route_dir, _)| route_dir.route_id.as_deref() == Some(route_id))
+            .flat_map(|(_, stops)| stops)
+            .unique_by(|stop| &stop.id);
+        for stop in stops {
+            let Some(point) = radius::stop_point(stop) else {
+                continue;
+            };
+            writeln!(kml, "<Placemark>")?;
+            writeln!(
+                kml,
+                "<name>{}</name>",
+                html_escape(&types::format_stop(stop, args))
+            )?;
+            writeln!(
+                kml,
+                "<Point><coordinates>{},{}</coordinates></Point>",
+                point.x(),
+                point.y()
+            )?;
+            writeln!(kml, "</Placemark>")?;
+        }
+        writeln!(kml, "</Folder>")?;
+    }
+    writeln!(kml, "</Document>")?;
+    writeln!(kml, "</kml>")?;
+
+    std::fs::write(out, kml)?;
+    log::info!("Wrote {}", out.display());
+
+    Ok(())
 }
 
-fn log_gtfs_info(source: &str, gtfs: &Gtfs) {
+fn trip_schedule(gtfs: Gtfs, args: &Args, trip_id: &str) -> anyhow::Result<()> {
+    let trip = gtfs
+        .trips
+        .get(trip_id)
+        .ok_or_else(|| anyhow::anyhow!("No trip with id {trip_id}"))?;
+
+    println!("Stop | Time");
+    println!("--- | --:");
+    for stop_time in &trip.stop_times {
+        let time = match (stop_time.arrival_time, stop_time.departure_time) {
+            (Some(arrival), Some(departure)) if arrival != departure => format!(
+                "arr. {} / dep. {}",
+                format_time_optional(Some(arrival), args.time_format),
+                format_time_optional(Some(departure), args.time_format)
+            ),
+            (arrival, departure) => {
+                format_time_optional(arrival.or(departure), args.time_format).into_owned()
+            }
+        };
+        println!("{} | {time}", types::format_stop(&stop_time.stop, args));
+    }
+
+    Ok(())
+}
+
+fn speed_check(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    let mut trips = gtfs.trips.values().collect::<Vec<_>>();
+    trips.sort_by(|a, b| a.id.cmp(&b.id));
+
+    println!("Trip | From | To | Speed (km/h)");
+    println!("--- | --- | --- | --:");
+    for trip in trips {
+        for (prev, next) in trip.stop_times.iter().zip(trip.stop_times.iter().skip(1)) {
+            let (Some(p1), Some(p2)) = (
+                radius::stop_point(&prev.stop),
+                radius::stop_point(&next.stop),
+            ) else {
+                continue;
+            };
+            let (Some(departure), Some(arrival)) = (
+                prev.departure_time.or(prev.arrival_time),
+                next.arrival_time.or(next.departure_time),
+            ) else {
+                continue;
+            };
+            if arrival <= departure {
+                continue;
+            }
+            let hours = f64::from(arrival - departure) / (60.0 * 60.0);
+            let speed = radius::distance(&p1, &p2, args.metric) / hours;
+            if speed > args.max_speed {
+                println!(
+                    "{} | {} | {} | {speed:.1}",
+                    trip.id,
+                    types::format_stop(&prev.stop, args),
+                    types::format_stop(&next.stop, args),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn timepoints(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
+
+    use gtfs_structures::TimepointType;
+
+    let stops_by_route = merge::stops_by_route(gtfs.trips.values(), args)?;
+
+    let mut timepoints_by_route = BTreeMap::new();
+    for trip in gtfs.trips.values() {
+        let timepoints = timepoints_by_route
+            .entry(types::RouteDir::from_trip(trip, args))
+            .or_insert_with(HashSet::new);
+        for stop_time in &trip.stop_times {
+            if matches!(stop_time.timepoint, TimepointType::Exact) {
+                timepoints.insert(&stop_time.stop.id);
+            }
+        }
+    }
+
+    output::print_sections(stops_by_route, &gtfs, args, |route, stops| {
+        let timepoints = timepoints_by_route.get(route);
+        if stops.is_empty() && args.note_empty {
+            output::print_note(output::EMPTY_NOTE, args.format);
+        }
+        for stop in stops {
+            let is_timepoint = timepoints.is_some_and(|tps| tps.contains(&stop.id));
+            println!(
+                "- [{}] {}",
+                if is_timepoint { "x" } else { " " },
+                types::format_stop(&stop, args)
+            );
+        }
+        println!();
+        Ok(())
+    })
+}
+
+fn name_check(gtfs: Gtfs, _args: &Args) -> anyhow::Result<()> {
+    use std::collections::HashMap;
+
+    use itertools::Itertools;
+
+    // count the alphabetic prefixes of each route's trip names
+    let mut prefixes_by_route = HashMap::<_, HashMap<_, usize>>::new();
+    for trip in gtfs.trips.values() {
+        let prefixes = prefixes_by_route.entry(&trip.route_id).or_default();
+        if let Some(name) = &trip.trip_short_name {
+            let prefix = name
+                .chars()
+                .take_while(|c| c.is_alphabetic())
+                .collect::<String>();
+            *prefixes.entry(prefix).or_default() += 1;
+        }
+    }
+
+    for (id, route) in gtfs.routes.iter().sorted_by_key(|(id, _)| *id) {
+        if route.short_name.is_empty() && route.long_name.is_empty() {
+            println!("- `{id}`: no short or long name");
+            continue;
+        }
+        if route.short_name.is_empty() {
+            continue;
+        }
+        let Some(prefixes) = prefixes_by_route.get(id) else {
+            continue;
+        };
+        let total = prefixes.values().sum::<usize>();
+        let common = prefixes
+            .iter()
+            .filter(|(prefix, _)| !prefix.is_empty())
+            .max_by_key(|(prefix, &count)| (count, std::cmp::Reverse(*prefix)));
+        if let Some((prefix, &count)) = common {
+            let matches_route = route
+                .short_name
+                .to_lowercase()
+                .starts_with(&prefix.to_lowercase());
+            if count * 2 > total && !matches_route {
+                println!(
+                    "- `{id}`: {count} of {total} trip names start with \"{prefix}\", \
+                     but the route short name is \"{}\"",
+                    route.short_name
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the feed's route colors, from the bytes read by [`read_source`], or else from the
+/// feed's directory or zip file.
+fn route_colors(source: &str, bytes: Option<&[u8]>) -> anyhow::Result<Vec<validate::ColorIssue>> {
+    let path = std::path::Path::new(source);
+    match bytes {
+        Some(bytes) => validate::check_zipped_route_colors(std::io::Cursor::new(bytes)),
+        None if path.is_dir() => {
+            validate::check_route_colors(std::fs::File::open(path.join("routes.txt"))?)
+        }
+        None => validate::check_zipped_route_colors(std::fs::File::open(path)?),
+    }
+}
+
+fn print_route_colors(issues: &[validate::ColorIssue]) {
+    println!("## Route colors");
+    println!();
+    println!("Route | Field | Value | Normalized");
+    println!("--- | --- | --- | ---");
+    for issue in issues {
+        let normalized = issue.normalized.as_deref().unwrap_or("—");
+        println!(
+            "{} | {} | `{}` | {normalized}",
+            issue.route_id, issue.field, issue.value
+        );
+    }
+    println!();
+}
+
+fn validate(gtfs: Gtfs, args: &Args, color_issues: &[validate::ColorIssue]) -> anyhow::Result<()> {
+    use itertools::Itertools;
+
+    use crate::validate::StopTimeIssueKind;
+
+    print_route_colors(color_issues);
+
+    println!("## Stop times");
+    println!();
+    println!("Trip | Stop sequence | Problem | Times");
+    println!("--- | --: | --- | ---");
+    for issue in validate::check_stop_times(&gtfs) {
+        let (problem, first, second) = match issue.kind {
+            StopTimeIssueKind::NegativeDwell { arrival, departure } => {
+                ("departure before arrival", arrival, departure)
+            }
+            StopTimeIssueKind::Decreasing { previous, current } => {
+                ("earlier than previous stop", previous, current)
+            }
+        };
+        println!(
+            "{} | {} | {problem} | {} → {}",
+            issue.trip_id,
+            issue.stop_sequence,
+            format_time_optional(Some(first), args.time_format),
+            format_time_optional(Some(second), args.time_format),
+        );
+    }
+    println!();
+
+    println!("## Duplicate stop locations");
+    println!();
+    println!("Location | Stops");
+    println!("--- | ---");
+    for group in validate::find_duplicate_locations(&gtfs, args.duplicate_distance / 1000.0) {
+        let location = radius::stop_point(group[0]).expect("stop without coordinates");
+        println!(
+            "{:.6}, {:.6} | {}",
+            location.y(),
+            location.x(),
+            group.iter().map(|stop| format!("`{}`", stop.id)).join(", ")
+        );
+    }
+    println!();
+
+    println!("## Untimed stop times");
+    println!();
+    println!("Route | Untimed | Total | Percent");
+    println!("--- | --: | --: | --:");
+    let untimed = validate::count_untimed_stop_times(&gtfs, args);
+    for (route, (untimed, total)) in output::sort_sections(untimed, &gtfs, args) {
+        if untimed > 0 {
+            println!(
+                "{} | {untimed} | {total} | {:.1}%",
+                route.format(args, &gtfs.routes),
+                100.0 * untimed as f64 / total as f64
+            );
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+fn signatures(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::BTreeSet;
+
+    use crate::multimap::MultiMap;
+
+    let patterns_by_route = gtfs
+        .trips
+        .values()
+        .map(|trip| {
+            let stop_ids = trip
+                .stop_times
+                .iter()
+                .map(|st| st.stop.id.as_str())
+                .collect::<Vec<_>>();
+            (types::RouteDir::from_trip(trip, args), stop_ids)
+        })
+        .collect::<MultiMap<_, _>>();
+
+    println!("Route | Direction | Signature");
+    println!("--- | --- | ---");
+    for (route, patterns) in output::sort_sections(patterns_by_route, &gtfs, args) {
+        // the merged stop order between branches is unspecified, so hash the (sorted) distinct
+        // patterns instead
+        let patterns = patterns.into_iter().collect::<BTreeSet<_>>();
+        let mut hash = Fnv1a::new();
+        for pattern in patterns {
+            for stop_id in pattern {
+                hash.write(stop_id.as_bytes());
+                hash.write(&[0x1f]);
+            }
+            hash.write(&[0x1e]);
+        }
+        println!(
+            "{} | {:?} | {:016x}",
+            route.route_id.as_deref().unwrap_or(""),
+            route.direction,
+            hash.finish()
+        );
+    }
+
+    Ok(())
+}
+
+fn transfer_rules(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use itertools::Itertools;
+
+    let routes_by_stop = routes_by_stop(&gtfs, args);
+    let format_routes = |stop_id: &str| {
+        routes_by_stop
+            .get(stop_id)
+            .map(|routes| routes.iter().join(", "))
+            .unwrap_or_default()
+    };
+    let format_stop_id = |stop_id: &str| match gtfs.stops.get(stop_id) {
+        Some(stop) => types::format_stop(stop, args).into_owned(),
+        None => format!("`{stop_id}`"),
+    };
+
+    println!("From | Routes | To | Routes | Type | Min time (s)");
+    println!("--- | --- | --- | --- | --- | --:");
+    let stops = gtfs
+        .stops
+        .values()
+        .sorted_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+    for stop in stops {
+        for transfer in &stop.transfers {
+            if !routes_by_stop.contains_key(stop.id.as_str())
+                && !routes_by_stop.contains_key(transfer.to_stop_id.as_str())
+            {
+                continue;
+            }
+            println!(
+                "{} | {} | {} | {} | {} | {}",
+                types::format_stop(stop, args),
+                format_routes(&stop.id),
+                format_stop_id(&transfer.to_stop_id),
+                format_routes(&transfer.to_stop_id),
+                transfers::transfer_type_label(transfer.transfer_type),
+                transfer
+                    .min_transfer_time
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the names of the routes serving each stop, by stop id.
+fn routes_by_stop<'a>(
+    gtfs: &'a Gtfs,
+    args: &'a Args,
+) -> std::collections::HashMap<&'a str, std::collections::BTreeSet<&'a str>> {
+    let mut routes_by_stop = std::collections::HashMap::<_, std::collections::BTreeSet<_>>::new();
+    for trip in gtfs.trips.values() {
+        let route = types::format_route(&trip.route_id, args, &gtfs.routes);
+        for stop_time in &trip.stop_times {
+            routes_by_stop
+                .entry(stop_time.stop.id.as_str())
+                .or_default()
+                .insert(route);
+        }
+    }
+    routes_by_stop
+}
+
+fn stations(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use itertools::Itertools;
+
+    use crate::multimap::MultiMap;
+
+    let routes_by_stop = routes_by_stop(&gtfs, args);
+    let children_by_station = gtfs
+        .stops
+        .values()
+        .filter_map(|stop| Some((stop.parent_station.as_deref()?, stop)))
+        .collect::<MultiMap<_, _>>();
+    let format_routes = |stop_id: &str| {
+        routes_by_stop
+            .get(stop_id)
+            .map(|routes| routes.iter().join(", "))
+            .unwrap_or_default()
+    };
+
+    let top_level = gtfs
+        .stops
+        .values()
+        .filter(|stop| stop.parent_station.is_none())
+        .sorted_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+    for stop in top_level {
+        let name = types::format_stop(stop, args);
+        if let Some(children) = children_by_station.get(stop.id.as_str()) {
+            if !children
+                .iter()
+                .any(|child| routes_by_stop.contains_key(child.id.as_str()))
+            {
+                continue;
+            }
+            println!("- **{name}** (`{}`)", stop.id);
+            for child in children
+                .iter()
+                .sorted_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)))
+            {
+                println!(
+                    "  - {} (`{}`): {}",
+                    types::format_stop(child, args),
+                    child.id,
+                    format_routes(&child.id)
+                );
+            }
+        } else if routes_by_stop.contains_key(stop.id.as_str()) {
+            println!("- {name} (`{}`): {}", stop.id, format_routes(&stop.id));
+        }
+    }
+
+    Ok(())
+}
+
+fn branch_summary(gtfs: Gtfs, args: &Args) -> anyhow::Result<()> {
+    use std::collections::{BTreeMap, HashMap};
+
+    use itertools::Itertools;
+
+    let mut endpoints_by_route = BTreeMap::<_, HashMap<_, _>>::new();
+    for trip in gtfs.trips.values() {
+        let Some(last) = trip.stop_times.last() else {
+            continue;
+        };
+        endpoints_by_route
+            .entry(types::RouteDir::from_trip(trip, args))
+            .or_default()
+            .entry(&last.stop.id)
+            .or_insert((&last.stop, 0))
+            .1 += 1;
+    }
+
+    output::print_sections(endpoints_by_route, &gtfs, args, |_, endpoints| {
+        println!();
+        println!("Endpoint | Trips");
+        println!("--- | --:");
+        for (stop, count) in endpoints
+            .into_values()
+            .sorted_by(|(a, a_count), (b, b_count)| {
+                b_count.cmp(a_count).then_with(|| a.name.cmp(&b.name))
+            })
+        {
+            println!("{} | {count}", types::format_stop(stop, args));
+        }
+        println!();
+        Ok(())
+    })
+}
+
+/// A 64-bit FNV-1a hash. Unlike `DefaultHasher`, its output is guaranteed to be the same across
+/// builds, so it is suitable for hashes which are persisted.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Returns the median of a non-empty sorted slice.
+fn median(sorted: &[usize]) -> f64 {
+    let mid = sorted.len() / 2;
+    match sorted.len() % 2 {
+        0 => (sorted[mid - 1] + sorted[mid]) as f64 / 2.0,
+        _ => sorted[mid] as f64,
+    }
+}
+
+fn format_time_optional(time: Option<u32>, format: TimeFormat) -> std::borrow::Cow<'static, str> {
+    use std::borrow::Cow;
+
+    let Some(time) = time else {
+        return Cow::Borrowed("");
+    };
+    let hrs = time / (60 * 60);
+    let mins = (time / 60) % 60;
+    let secs = time % 60;
+    match format {
+        TimeFormat::Clock24h => Cow::Owned(format!("{:02}:{:02}:{:02}", hrs, mins, secs)),
+        TimeFormat::Clock12h => {
+            let (days, hrs) = (hrs / 24, hrs % 24);
+            let meridiem = if hrs < 12 { "AM" } else { "PM" };
+            let hrs = if hrs % 12 == 0 { 12 } else { hrs % 12 };
+            let mut clock = format!("{hrs}:{mins:02}");
+            if secs > 0 {
+                clock += &format!(":{secs:02}");
+            }
+            clock += &format!(" {meridiem}");
+            if days > 0 {
+                clock += &format!(" (+{days})");
+            }
+            Cow::Owned(clock)
+        }
+        TimeFormat::IsoDuration => {
+            if time == 0 {
+                return Cow::Borrowed("PT0S");
+            }
+            let mut duration = String::from("PT");
+            for (value, unit) in [(hrs, 'H'), (mins, 'M'), (secs, 'S')] {
+                if value > 0 {
+                    duration += &format!("{value}{unit}");
+                }
+            }
+            Cow::Owned(duration)
+        }
+    }
+}
+
+/// Reads the zipped feed from stdin if `source` is `-`, or downloads it if `source` is an
+/// `http://` or `https://` URL. Local paths are left for [`load_gtfs`] to read, and give `None`.
+fn read_source(source: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    use std::io::Read;
+
+    if source == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        return Ok(Some(bytes));
+    }
+    if !(source.starts_with("http://") || source.starts_with("https://")) {
+        return Ok(None);
+    }
+    let response = reqwest::blocking::get(source)?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("Failed to download {source}: HTTP {status}");
+    }
+    Ok(Some(response.bytes()?.to_vec()))
+}
+
+/// Loads the feed from the bytes read by [`read_source`], or else from the local path `source`.
+fn load_gtfs(source: &str, bytes: Option<&[u8]>) -> anyhow::Result<Gtfs> {
+    match bytes {
+        Some(bytes) => Ok(Gtfs::from_reader(std::io::Cursor::new(bytes))?),
+        None => Ok(Gtfs::new(source)?),
+    }
+}
+
+fn log_gtfs_info(source: &str, gtfs: &Gtfs, bytes_read: Option<usize>) {
     log::info!("Loaded GTFS data from {}:", source);
+    if let Some(bytes) = bytes_read {
+        log::info!("  Read {bytes} bytes");
+    }
     log::info!("  Read in {} ms", gtfs.read_duration);
     log::info!("  Stops: {}", gtfs.stops.len());
     log::info!("  Routes: {}", gtfs.routes.len());
@@ -271,3 +2924,60 @@ fn log_gtfs_info(source: &str, gtfs: &Gtfs) {
     log::info!("  Fare attributes: {}", gtfs.fare_attributes.len());
     log::info!("  Feed info: {}", gtfs.feed_info.len());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_duration() {
+        let format = |time| format_time_optional(Some(time), TimeFormat::IsoDuration);
+        assert_eq!(format(0), "PT0S");
+        assert_eq!(format(7 * 3600 + 30 * 60), "PT7H30M");
+        assert_eq!(format(25 * 3600 + 15 * 60), "PT25H15M");
+    }
+
+    #[test]
+    fn clock_12h() {
+        let format = |time| format_time_optional(Some(time), TimeFormat::Clock12h);
+        assert_eq!(format(0), "12:00 AM");
+        assert_eq!(format(7 * 3600 + 30 * 60), "7:30 AM");
+        assert_eq!(format(12 * 3600 + 5), "12:00:05 PM");
+        assert_eq!(format(15 * 3600), "3:00 PM");
+        assert_eq!(format(24 * 3600), "12:00 AM (+1)");
+        assert_eq!(format(25 * 3600 + 15 * 60), "1:15 AM (+1)");
+        assert_eq!(format(48 * 3600 + 60), "12:01 AM (+2)");
+    }
+
+    #[test]
+    fn stop_time_order() {
+        use gtfs_structures::StopTime;
+
+        // stop 2 has no times; the others depart a minute apart, in sequence
+        let stop_time = |stop_sequence, time| StopTime {
+            stop_sequence,
+            arrival_time: time,
+            departure_time: time,
+            ..Default::default()
+        };
+        let shuffled = || {
+            vec![
+                stop_time(3, Some(180)),
+                stop_time(1, Some(60)),
+                stop_time(4, Some(240)),
+                stop_time(2, None),
+            ]
+        };
+        let sorted = |order: StopTimeOrder| {
+            let mut stop_times = shuffled();
+            order.sort(&mut stop_times);
+            stop_times
+                .iter()
+                .map(|st| st.stop_sequence)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(StopTimeOrder::Sequence), [1, 2, 3, 4]);
+        // the untimed stop is sorted first
+        assert_eq!(sorted(StopTimeOrder::Departure), [2, 1, 3, 4]);
+    }
+}
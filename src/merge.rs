@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use gtfs_structures::{Stop, Trip};
 use itertools::Itertools;
@@ -7,27 +10,65 @@ use crate::{multimap::MultiMap, types::RouteDir};
 
 mod dag;
 
+/// How many of the slowest routes to report under `--verbose`.
+const SLOWEST_ROUTES: usize = 5;
+
 pub fn stops_by_route<'a>(
     trips: impl IntoIterator<Item = &'a Trip>,
     args: &crate::Args,
 ) -> anyhow::Result<MultiMap<RouteDir, Arc<Stop>>> {
-    // first, collect trips by route id and direction
-    let trips_by_route = trips
+    merge_routes(&trips_by_route(trips, args), args)
+}
+
+/// Collects trips by route id and direction.
+pub fn trips_by_route<'a>(
+    trips: impl IntoIterator<Item = &'a Trip>,
+    args: &crate::Args,
+) -> MultiMap<RouteDir, &'a Trip> {
+    trips
         .into_iter()
-        .map(|trip| {
-            (
-                RouteDir::from_trip(trip, args),
-                trip,
-            )
-        })
-        .collect::<MultiMap<_, _>>();
+        .map(|trip| (RouteDir::from_trip(trip, args), trip))
+        .collect()
+}
 
-    // then, merge all trips into a consolidated list of stops
+/// Merges the trips of each route/direction pair into a consolidated list of stops.
+pub fn merge_routes(
+    trips_by_route: &MultiMap<RouteDir, &Trip>,
+    args: &crate::Args,
+) -> anyhow::Result<MultiMap<RouteDir, Arc<Stop>>> {
     let mut stops_by_route = MultiMap::new();
-    for (route, trips) in trips_by_route.map {
-        let trips =
-            merge_trips(trips).map_err(|err| anyhow::anyhow!("{err} in route {route:?}"))?;
-        stops_by_route.insert_bulk(route, trips);
+    for (route, stops) in merge_routes_with_depths(trips_by_route, args)? {
+        stops_by_route.insert_bulk(route, stops.into_iter().map(|(stop, _)| stop).collect());
+    }
+    Ok(stops_by_route)
+}
+
+/// Merges the trips of each route/direction pair into a consolidated list of stops, like
+/// [`merge_routes`], pairing each stop with its depth: the greatest number of stops before it on
+/// any path through the route's trips. Stops at the same depth are on parallel branches.
+pub fn merge_routes_with_depths(
+    trips_by_route: &MultiMap<RouteDir, &Trip>,
+    args: &crate::Args,
+) -> anyhow::Result<MultiMap<RouteDir, (Arc<Stop>, usize)>> {
+    let mut stops_by_route = MultiMap::new();
+    let mut timings = Vec::new();
+    for (route, trips) in trips_by_route {
+        if let Some(ratio) = args.short_trip_ratio {
+            warn_short_trips(route, trips, ratio);
+        }
+        let num_trips = trips.len();
+        let start = Instant::now();
+        let stops = merge_trips(route, trips)?;
+        let elapsed = start.elapsed();
+        if args.warn_duplicate_names {
+            warn_duplicate_names(route, stops.iter().map(|(stop, _)| stop.as_ref()));
+        }
+        log::debug!("merged {num_trips} trips in route {route:?} in {elapsed:?}");
+        timings.push((elapsed, num_trips, route.clone()));
+        stops_by_route.insert_bulk(route.clone(), stops);
+    }
+    if args.verbose {
+        report_slowest(timings);
     }
     Ok(stops_by_route)
 }
@@ -39,17 +80,12 @@ pub fn stops_by_route_unsorted<'a>(
     // first, collect trips by route id and direction
     let trips_by_route = trips
         .into_iter()
-        .map(|trip| {
-            (
-                RouteDir::from_trip(trip, args),
-                trip,
-            )
-        })
+        .map(|trip| (RouteDir::from_trip(trip, args), trip))
         .collect::<MultiMap<_, _>>();
 
     // then, merge all trips into a consolidated list of stops
     let mut stops_by_route = MultiMap::new();
-    for (route, trips) in trips_by_route.map {
+    for (route, trips) in trips_by_route {
         let stops = trips
             .into_iter()
             .flat_map(|trip| &trip.stop_times)
@@ -61,7 +97,53 @@ pub fn stops_by_route_unsorted<'a>(
     Ok(stops_by_route)
 }
 
-fn merge_trips(trips: Vec<&Trip>) -> anyhow::Result<Vec<Arc<Stop>>> {
+/// Warns if the route/direction's shortest trip has less than `ratio` times as many stops as its
+/// longest trip, since short-turn or partial trips can make the merged stop list misleading.
+fn warn_short_trips(route: &RouteDir, trips: &[&Trip], ratio: f64) {
+    let shortest = trips
+        .iter()
+        .min_by_key(|trip| (trip.stop_times.len(), &trip.id));
+    let longest = trips
+        .iter()
+        .max_by_key(|trip| (trip.stop_times.len(), std::cmp::Reverse(&trip.id)));
+    if let (Some(shortest), Some(longest)) = (shortest, longest) {
+        let (min, max) = (shortest.stop_times.len(), longest.stop_times.len());
+        if (min as f64) < ratio * max as f64 {
+            log::warn!(
+                "{route:?}: trip {} has only {min} stops, but trip {} has {max}",
+                shortest.id,
+                longest.id
+            );
+        }
+    }
+}
+
+/// Warns about stops in the route/direction's merged stop list which share a name but not an id,
+/// since they show up as the same stop repeated.
+fn warn_duplicate_names<'a>(route: &RouteDir, stops: impl IntoIterator<Item = &'a Stop>) {
+    let by_name = stops
+        .into_iter()
+        .map(|stop| (stop.name.as_str(), stop.id.as_str()))
+        .into_group_map();
+    for (name, ids) in by_name.into_iter().sorted() {
+        if ids.len() > 1 {
+            log::warn!(
+                "{route:?}: stop name {name:?} is shared by stops {}",
+                ids.join(", ")
+            );
+        }
+    }
+}
+
+fn report_slowest(mut timings: Vec<(Duration, usize, RouteDir)>) {
+    timings.sort_by_key(|(elapsed, _, _)| std::cmp::Reverse(*elapsed));
+    eprintln!("Slowest routes to merge:");
+    for (elapsed, num_trips, route) in timings.into_iter().take(SLOWEST_ROUTES) {
+        eprintln!("  {route:?}: {elapsed:?} ({num_trips} trips)");
+    }
+}
+
+fn merge_trips(route: &RouteDir, trips: &[&Trip]) -> anyhow::Result<Vec<(Arc<Stop>, usize)>> {
     use dag::{Dag, PtrKey};
     // generate dag from trips
     let mut dag = Dag::<PtrKey<Stop>, Arc<Stop>>::new();
@@ -69,9 +151,47 @@ fn merge_trips(trips: Vec<&Trip>) -> anyhow::Result<Vec<Arc<Stop>>> {
         let mut parent = None;
         for st in &trip.stop_times {
             let child = st.stop.clone();
-            dag.insert_child(parent, child.clone())?;
+            dag.insert_child(parent, child.clone())
+                .map_err(|err| anyhow::anyhow!("{err} in route {route:?}"))?;
+            parent = Some(child);
+        }
+    }
+    dag.flatten_with_levels().map_err(|cycle| {
+        let stops = cycle.0.iter().map(|stop| &stop.name).join(", ");
+        match find_cycle_edge(trips) {
+            Some((trip, parent, child)) => anyhow::anyhow!(
+                "Cycle in route {route:?}: trip {trip} goes from {} ({}) to {} ({}), but earlier \
+                trips go the other way. Stops involved: {stops}",
+                parent.name,
+                parent.id,
+                child.name,
+                child.id,
+            ),
+            None => anyhow::anyhow!("Cycle in route {route:?} involving stops: {stops}"),
+        }
+    })
+}
+
+/// Rebuilds the graph for a route which failed to flatten, checking each edge as it is inserted,
+/// and returns the first trip and pair of stops which close a cycle.
+///
+/// The check walks the graph for every new edge, so it is only run once a cycle is known to exist.
+fn find_cycle_edge<'a>(trips: &[&'a Trip]) -> Option<(&'a str, Arc<Stop>, Arc<Stop>)> {
+    use dag::{Dag, InsertError, PtrKey};
+    let mut dag = Dag::<PtrKey<Stop>, Arc<Stop>>::new();
+    for trip in trips {
+        let mut parent = None;
+        for st in &trip.stop_times {
+            let child = st.stop.clone();
+            match dag.try_insert_child(parent, child.clone()) {
+                Ok(()) => {}
+                Err(InsertError::Cycle { parent, child }) => {
+                    return Some((trip.id.as_str(), parent, child))
+                }
+                Err(InsertError::ParentNotFound) => return None,
+            }
             parent = Some(child);
         }
     }
-    dag.flatten()
+    None
 }
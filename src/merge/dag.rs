@@ -69,7 +69,54 @@ where
         Ok(())
     }
 
-    pub fn flatten(self) -> anyhow::Result<Vec<V>> {
+    /// Inserts a node, like [`insert_child`](Self::insert_child), but first checks that the edge
+    /// from `parent` wouldn't create a cycle, so that the edge which causes a cycle can be
+    /// reported, rather than every node on it.
+    ///
+    /// The check walks everything reachable from `child`, so building a whole graph this way is
+    /// quadratic. Prefer `insert_child`, and only use this to diagnose a graph which failed to
+    /// flatten.
+    pub fn try_insert_child(&mut self, parent: Option<V>, child: V) -> Result<(), InsertError<V>> {
+        if let Some(parent_idx) = parent.as_ref().map(K::from) {
+            let child_idx = K::from(&child);
+            let parent_node = self
+                .nodes
+                .get(&parent_idx)
+                .ok_or(InsertError::ParentNotFound)?;
+            // an existing edge can't create a new cycle
+            if !parent_node.children.contains(&child_idx) && self.reaches(child_idx, parent_idx) {
+                return Err(InsertError::Cycle {
+                    parent: parent.expect("parent is some"),
+                    child,
+                });
+            }
+        }
+        self.insert_child(parent, child)
+            .map_err(|_| InsertError::ParentNotFound)
+    }
+
+    /// Returns whether there is a path from `from` to `to`, following edges from parent to child.
+    fn reaches(&self, from: K, to: K) -> bool {
+        let mut stack = vec![from];
+        let mut visited = BTreeSet::new();
+        while let Some(idx) = stack.pop() {
+            if idx == to {
+                return true;
+            }
+            if visited.insert(idx) {
+                if let Some(node) = self.nodes.get(&idx) {
+                    stack.extend(node.children.iter().copied());
+                }
+            }
+        }
+        false
+    }
+
+    /// Flattens the graph in topological order, pairing each node with its level: the length of
+    /// the longest path to it from a node with no parents.
+    ///
+    /// If the graph has a cycle, returns the nodes on (or between) cycles.
+    pub fn flatten_with_levels(self) -> Result<Vec<(V, usize)>, Cycle<V>> {
         // all nodes with no parents
         let mut heads = Vec::new();
         // all other nodes
@@ -84,10 +131,14 @@ where
         }
         log::debug!("{} heads; {} tails", heads.len(), tails.len());
 
+        let mut levels = BTreeMap::new();
         let mut output = Vec::new();
         while let Some((idx, node)) = heads.pop() {
-            output.push(node.value);
+            let level = levels.remove(&idx).unwrap_or(0);
+            output.push((node.value, level));
             for ch_idx in node.children {
+                let child_level = levels.entry(ch_idx).or_insert(0);
+                *child_level = cmp::max(*child_level, level + 1);
                 match tails.entry(ch_idx) {
                     Entry::Occupied(mut entry) => {
                         if !entry.get_mut().remove_parent(idx) {
@@ -106,12 +157,40 @@ where
             // successfully processed all nodes
             Ok(output)
         } else {
-            // some nodes were never processed, therefore there was a cycle
-            Err(anyhow::anyhow!("Cycle in graph"))
+            // some nodes were never processed, therefore there was a cycle. trim off the nodes
+            // which don't lead back into a cycle, leaving the nodes on (or between) cycles
+            loop {
+                let sinks = tails
+                    .iter()
+                    .filter(|(_, node)| node.children.iter().all(|ch| !tails.contains_key(ch)))
+                    .map(|(idx, _)| *idx)
+                    .collect::<Vec<_>>();
+                if sinks.is_empty() {
+                    break;
+                }
+                for idx in sinks {
+                    tails.remove(&idx);
+                }
+            }
+            Err(Cycle(tails.into_values().map(|node| node.value).collect()))
         }
     }
 }
 
+/// An error inserting a node into a graph.
+#[derive(Debug)]
+pub enum InsertError<V> {
+    /// The parent isn't in the graph.
+    ParentNotFound,
+    /// The child is already an ancestor of the parent, so the edge between them would create a
+    /// cycle.
+    Cycle { parent: V, child: V },
+}
+
+/// The nodes of a graph which are on, or between, cycles, and so couldn't be flattened.
+#[derive(Debug)]
+pub struct Cycle<V>(pub Vec<V>);
+
 #[derive(Debug)]
 struct Node<K, V> {
     value: V,
@@ -143,3 +222,53 @@ where
         self.children.insert(child);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    type TestDag = Dag<PtrKey<usize>, Arc<usize>>;
+
+    #[test]
+    fn cycles() {
+        let [a, b, c] = [0, 1, 2].map(Arc::new);
+        let edges = [(&a, &b), (&b, &c), (&c, &a)];
+
+        // without the check, the cycle is only found when flattening
+        let mut dag = TestDag::new();
+        dag.insert_child(None, a.clone()).unwrap();
+        for (parent, child) in edges {
+            dag.insert_child(Some(parent.clone()), child.clone())
+                .unwrap();
+        }
+        let Err(Cycle(mut cycle)) = dag.flatten_with_levels() else {
+            panic!("flattened a cycle");
+        };
+        cycle.sort();
+        assert_eq!(cycle, [a.clone(), b.clone(), c.clone()]);
+
+        // with it, the edge which closes the cycle is rejected
+        let mut dag = TestDag::new();
+        dag.try_insert_child(None, a.clone()).unwrap();
+        for (parent, child) in &edges[..2] {
+            dag.try_insert_child(Some((*parent).clone()), (*child).clone())
+                .unwrap();
+        }
+        // repeating an edge is fine
+        dag.try_insert_child(Some(a.clone()), b.clone()).unwrap();
+        match dag.try_insert_child(Some(c.clone()), a.clone()) {
+            Err(InsertError::Cycle { parent, child }) => {
+                assert_eq!((*parent, *child), (2, 0));
+            }
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+        let flat = dag.flatten_with_levels().unwrap();
+        let levels = flat
+            .iter()
+            .map(|(v, level)| (**v, *level))
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [(0, 0), (1, 1), (2, 2)]);
+    }
+}
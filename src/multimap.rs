@@ -1,8 +1,14 @@
-use std::collections::{btree_map::Entry, BTreeMap};
+// `MultiMap` is a general-purpose container, and not every method has a caller in every command.
+#![allow(dead_code)]
+
+use std::{
+    borrow::Borrow,
+    collections::{btree_map, btree_map::Entry, BTreeMap},
+};
 
 #[derive(Debug)]
 pub struct MultiMap<K, V> {
-    pub map: BTreeMap<K, Vec<V>>,
+    map: BTreeMap<K, Vec<V>>,
 }
 
 impl<K, V> MultiMap<K, V>
@@ -29,6 +35,87 @@ where
             }
         }
     }
+
+    /// Returns the values for `key`, if there are any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key).map(Vec::as_slice)
+    }
+
+    /// Returns the values for `key` mutably, if there are any.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Vec<V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get_mut(key)
+    }
+
+    /// Returns whether there are any values for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Removes and returns the values for `key`, if there are any.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Vec<V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(key)
+    }
+
+    /// Keeps only the keys for which `f` returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut Vec<V>) -> bool) {
+        self.map.retain(|key, values| f(key, values));
+    }
+
+    /// Returns an iterator over the keys and their values, in key order.
+    pub fn iter(&self) -> btree_map::Iter<'_, K, Vec<V>> {
+        self.map.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, K, Vec<V>> {
+        self.map.iter_mut()
+    }
+
+    pub fn into_values(self) -> btree_map::IntoValues<K, Vec<V>> {
+        self.map.into_values()
+    }
+}
+
+impl<K, V> IntoIterator for MultiMap<K, V> {
+    type Item = (K, Vec<V>);
+    type IntoIter = btree_map::IntoIter<K, Vec<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a MultiMap<K, V> {
+    type Item = (&'a K, &'a Vec<V>);
+    type IntoIter = btree_map::Iter<'a, K, Vec<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut MultiMap<K, V> {
+    type Item = (&'a K, &'a mut Vec<V>);
+    type IntoIter = btree_map::IterMut<'a, K, Vec<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut()
+    }
 }
 
 impl<K, V> FromIterator<(K, V)> for MultiMap<K, V>
@@ -46,3 +133,49 @@ where
         map
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> MultiMap<&'static str, u32> {
+        [("b", 3), ("a", 1), ("b", 4), ("a", 2)]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn get() {
+        let mut map = sample();
+        assert_eq!(map.get("a"), Some(&[1, 2][..]));
+        assert_eq!(map.get("c"), None);
+        assert!(map.contains_key("b"));
+        assert!(!map.contains_key("c"));
+
+        map.get_mut("b").unwrap().push(5);
+        assert_eq!(map.get("b"), Some(&[3, 4, 5][..]));
+        assert!(map.get_mut("c").is_none());
+    }
+
+    #[test]
+    fn iter() {
+        let mut map = sample();
+        map.insert_bulk("a", vec![6]);
+        map.insert_bulk("c", vec![7, 8]);
+        let expected = [("a", vec![1, 2, 6]), ("b", vec![3, 4]), ("c", vec![7, 8])];
+        let borrowed = map.iter().map(|(k, v)| (*k, v.clone())).collect::<Vec<_>>();
+        assert_eq!(borrowed, expected);
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), expected);
+    }
+    #[test]
+    fn remove_and_retain() {
+        let mut map = sample();
+        map.insert("c", 5);
+        assert_eq!(map.remove("a"), Some(vec![1, 2]));
+        assert_eq!(map.remove("a"), None);
+
+        map.retain(|_, values| values.len() > 1);
+        assert!(map.contains_key("b"));
+        assert!(!map.contains_key("c"));
+    }
+}
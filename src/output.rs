@@ -0,0 +1,303 @@
+//! Ordering and headings for per-route output sections.
+
+use std::fmt;
+
+use gtfs_structures::{Gtfs, RouteType};
+
+use crate::{table::Format, types::RouteDir};
+
+/// Printed with [`print_note`] in place of a section's body under `--note-empty`, when there is no
+/// data to show.
+pub const EMPTY_NOTE: &str = "No data for this route/direction.";
+
+/// Quotes a field for CSV output, if necessary.
+///
+/// Only the characters with special meaning in CSV are escaped; anything else, including
+/// non-ASCII text, is written as-is, as UTF-8.
+pub fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// Escapes the characters with special meaning in HTML.
+pub fn html_escape(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.contains(['&', '<', '>', '"']) {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .into()
+    } else {
+        text.into()
+    }
+}
+
+/// Prints a heading at the given level (1 for the top level) in the selected `--format`.
+///
+/// In CSV, headings are printed as a row with a single field.
+pub fn print_heading(level: usize, text: &str, format: Format) {
+    println!("{}", heading(level, text, format));
+}
+
+/// Formats a heading, like [`print_heading`], without a trailing newline.
+pub fn heading(level: usize, text: &str, format: Format) -> String {
+    match format {
+        Format::Markdown => format!("{} {text}", "#".repeat(level)),
+        Format::Csv => csv_field(text).into_owned(),
+        Format::Html => format!("<h{level}>{}</h{level}>", html_escape(text)),
+        Format::Json => unreachable!("JSON output is only supported by route-summary"),
+    }
+}
+
+/// Prints a note, such as a footnote to a table, in the selected `--format`.
+///
+/// In CSV, notes are printed as a row with a single field, like headings.
+pub fn print_note(text: &str, format: Format) {
+    match format {
+        Format::Markdown => println!("_{text}_"),
+        Format::Csv => println!("{}", csv_field(text)),
+        Format::Html => println!("<p><em>{}</em></p>", html_escape(text)),
+        Format::Json => unreachable!("JSON output is only supported by route-summary"),
+    }
+}
+
+/// Prints a list of items in the selected `--format`.
+///
+/// In CSV, each item is printed as a row with a single field.
+pub fn print_list<I: fmt::Display>(items: impl IntoIterator<Item = I>, format: Format) {
+    print!("{}", list(items, format));
+}
+
+/// Formats a list of items, like [`print_list`], with each item on its own line.
+pub fn list<I: fmt::Display>(items: impl IntoIterator<Item = I>, format: Format) -> String {
+    use fmt::Write;
+
+    let mut out = String::new();
+    if let Format::Html = format {
+        out.push_str("<ul>\n");
+    }
+    for item in items {
+        let item = item.to_string();
+        // writing to a string can't fail
+        let _ = match format {
+            Format::Markdown => writeln!(out, "- {item}"),
+            Format::Csv => writeln!(out, "{}", csv_field(&item)),
+            Format::Html => writeln!(out, "<li>{}</li>", html_escape(&item)),
+            Format::Json => unreachable!("JSON output is only supported by route-summary"),
+        };
+    }
+    if let Format::Html = format {
+        out.push_str("</ul>\n");
+    }
+    out
+}
+
+/// Returns a file name stem for the route/direction pair, from its route id, direction, and
+/// headsign, with characters which may not be valid in file names replaced.
+pub fn file_stem(route: &RouteDir) -> String {
+    let route_id = route.route_id.as_deref().unwrap_or("all");
+    let mut name = format!("{route_id}_{:?}", route.direction);
+    if let Some(headsign) = &route.headsign {
+        name = format!("{name}_{headsign}");
+    }
+    name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+}
+
+/// Prints a route heading followed by the output of `body` for each route/direction pair,
+/// ordered and grouped according to `args`.
+pub fn print_sections<V>(
+    sections: impl IntoIterator<Item = (RouteDir, V)>,
+    gtfs: &Gtfs,
+    args: &crate::Args,
+    mut body: impl FnMut(&RouteDir, V) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let sections = sort_sections(sections, gtfs, args);
+
+    if args.toc && args.format == Format::Markdown {
+        let mut slugs = Slugs::default();
+        let mut current_group = None;
+        for (route, _) in &sections {
+            let mut indent = "";
+            if let Some(group) = group_name(route, gtfs, args) {
+                if current_group != Some(group) {
+                    println!("- [{group}](#{})", slugs.slug(group));
+                    current_group = Some(group);
+                }
+                indent = "  ";
+            }
+            let heading = route.format(args, &gtfs.routes);
+            println!("{indent}- [{heading}](#{})", slugs.slug(&heading));
+        }
+        println!();
+    }
+
+    let mut current_group = None;
+    for (route, value) in sections {
+        if let Some(group) = group_name(&route, gtfs, args) {
+            if current_group != Some(group) {
+                print_heading(1, group, args.format);
+                println!();
+                current_group = Some(group);
+            }
+        }
+        print_heading(2, &route.format(args, &gtfs.routes), args.format);
+        body(&route, value)?;
+    }
+    Ok(())
+}
+
+/// Generates GitHub-style anchor slugs for headings, disambiguating repeated headings the same
+/// way GitHub does (`heading`, `heading-1`, `heading-2`, ...).
+#[derive(Debug, Default)]
+struct Slugs {
+    seen: std::collections::HashMap<String, usize>,
+}
+
+impl Slugs {
+    fn slug(&mut self, heading: &str) -> String {
+        let slug = heading
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter(|&c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+            .map(|c| if c == ' ' { '-' } else { c })
+            .collect::<String>();
+        let count = self.seen.entry(slug.clone()).or_insert(0);
+        let slug = if *count == 0 {
+            slug
+        } else {
+            format!("{slug}-{count}")
+        };
+        *count += 1;
+        slug
+    }
+}
+
+/// Orders route/direction pairs for output. By default, this is the ordering of `RouteDir`;
+/// with `--group-by-agency` or `--group-by-type`, routes are ordered by agency name or route
+/// type, then by route name.
+pub fn sort_sections<V>(
+    sections: impl IntoIterator<Item = (RouteDir, V)>,
+    gtfs: &Gtfs,
+    args: &crate::Args,
+) -> Vec<(RouteDir, V)> {
+    let mut sections = sections.into_iter().collect::<Vec<_>>();
+    if args.group_by_agency || args.group_by_type {
+        sections.sort_by_cached_key(|(route, _)| {
+            (
+                group_name(route, gtfs, args).map(str::to_owned),
+                route.format(args, &gtfs.routes),
+                route.clone(),
+            )
+        });
+    } else {
+        sections.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+    sections
+}
+
+/// Returns the heading of the group the route belongs to, if grouping by `--group-by-agency` or
+/// `--group-by-type`.
+fn group_name<'a>(route: &RouteDir, gtfs: &'a Gtfs, args: &crate::Args) -> Option<&'a str> {
+    if args.group_by_agency {
+        Some(agency_name(route, gtfs))
+    } else if args.group_by_type {
+        let route_type = route
+            .route_id
+            .as_ref()
+            .and_then(|id| gtfs.routes.get(id))
+            .map(|route| route.route_type);
+        Some(route_type.map_or("Unknown type", route_type_label))
+    } else {
+        None
+    }
+}
+
+/// Returns a name for a GTFS route type. Extended route types which `gtfs_structures` doesn't
+/// recognize are grouped together.
+fn route_type_label(route_type: RouteType) -> &'static str {
+    match route_type {
+        RouteType::Tramway => "Tram",
+        RouteType::Subway => "Subway",
+        RouteType::Rail => "Rail",
+        RouteType::Bus => "Bus",
+        RouteType::Ferry => "Ferry",
+        RouteType::CableCar => "Cable tram",
+        RouteType::Gondola => "Aerial lift",
+        RouteType::Funicular => "Funicular",
+        RouteType::Coach => "Coach",
+        RouteType::Air => "Air",
+        RouteType::Taxi => "Taxi",
+        RouteType::Other(11) => "Trolleybus",
+        RouteType::Other(12) => "Monorail",
+        RouteType::Other(_) => "Other",
+    }
+}
+
+/// Returns the name of the agency operating the route. Routes without an `agency_id` belong to
+/// the feed's only agency, if there is exactly one.
+fn agency_name<'a>(route: &RouteDir, gtfs: &'a Gtfs) -> &'a str {
+    let agency_id = route
+        .route_id
+        .as_ref()
+        .and_then(|id| gtfs.routes.get(id))
+        .map(|route| route.agency_id.as_ref());
+    let agency = match agency_id {
+        Some(Some(id)) => gtfs
+            .agencies
+            .iter()
+            .find(|agency| agency.id.as_ref() == Some(id)),
+        Some(None) if gtfs.agencies.len() == 1 => gtfs.agencies.first(),
+        _ => None,
+    };
+    agency.map_or("Unknown agency", |agency| &agency.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+    use crate::table::{Align, Format, Table};
+
+    const NAMES: [&str; 2] = ["Champs-Élysées", "新宿駅西口"];
+
+    #[test]
+    fn csv_field_leaves_non_ascii_unquoted() {
+        for name in NAMES {
+            assert_eq!(csv_field(name), name);
+        }
+    }
+
+    #[test]
+    fn csv_round_trips_non_ascii() {
+        let mut table = Table::new(NAMES.to_vec());
+        table.push_column("Name", NAMES.to_vec()).unwrap();
+        let csv = table
+            .formatter_with(Format::Csv, |h| h, |r| r, |c| c, Align::Left)
+            .to_string();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(csv.as_bytes());
+        let records = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        // a header row, then a row for each name
+        assert_eq!(records.len(), NAMES.len() + 1);
+        for (record, name) in records[1..].iter().zip(NAMES) {
+            assert_eq!(record.get(0), Some(name));
+            assert_eq!(record.get(1), Some(name));
+        }
+    }
+
+    #[test]
+    fn json_round_trips_non_ascii() {
+        for name in NAMES {
+            let json = serde_json::to_string_pretty(&serde_json::json!({ "name": name })).unwrap();
+            // written as UTF-8, not escaped
+            assert!(json.contains(name));
+            let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+            assert_eq!(value["name"], name);
+        }
+    }
+}
@@ -10,18 +10,195 @@
 //! - `radius = points.flat_map(|i| points.map(|j| d(i, j)).max()).min()`
 //! - `diameter = points.flat_map(|i| points.map(|j| d(i, j)).max()).max()`
 
-use geo::{GeodesicDistance, Point};
+use geo::{GeodesicBearing, GeodesicDistance, HaversineDistance, Point, RhumbDistance};
+use gtfs_structures::{Stop, Trip};
 
-pub fn radius_and_diameter(points: &[Point]) -> (f64, f64) {
-    points
+/// Returns the location of a stop, if it has both coordinates.
+pub fn stop_point(stop: &Stop) -> Option<Point> {
+    stop.longitude
+        .and_then(|long| stop.latitude.map(|lat| Point::new(long, lat)))
+}
+
+/// How to measure the distance between two points.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum Metric {
+    /// The length of the shortest path between the points on the surface of the earth.
+    #[default]
+    Geodesic,
+    /// The length of the path between the points which keeps a constant bearing. This is
+    /// noticeably longer than the geodesic distance for long east-west paths away from the
+    /// equator. It is measured on a spherical earth, so for short paths it can come out slightly
+    /// shorter.
+    Rhumb,
+    /// The great-circle distance on a spherical earth. This is faster than the geodesic
+    /// distance, and within about 0.5% of it.
+    Haversine,
+    /// The straight-line distance on a flat projection of the earth around the points. This is
+    /// the fastest, and accurate enough for points within a city.
+    Euclidean,
+}
+
+/// Mean radius of the earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Returns the distance between two points, in km.
+pub fn distance(p1: &Point, p2: &Point, metric: Metric) -> f64 {
+    let meters = match metric {
+        Metric::Geodesic => p1.geodesic_distance(p2),
+        Metric::Rhumb => p1.rhumb_distance(p2),
+        Metric::Haversine => p1.haversine_distance(p2),
+        Metric::Euclidean => {
+            // equirectangular projection, centered between the points
+            let mean_lat = ((p1.y() + p2.y()) / 2.0).to_radians();
+            let dx = (p2.x() - p1.x()).to_radians() * mean_lat.cos();
+            let dy = (p2.y() - p1.y()).to_radians();
+            dx.hypot(dy) * EARTH_RADIUS
+        }
+    };
+    meters / 1000.0
+}
+
+/// Returns the initial compass bearing of the geodesic from `p1` to `p2`, in degrees clockwise
+/// from north, from 0 up to 360.
+pub fn bearing(p1: &Point, p2: &Point) -> f64 {
+    p1.geodesic_bearing(*p2).rem_euclid(360.0)
+}
+
+/// Returns the nearest of the eight principal compass directions (e.g. "NE") to a bearing in
+/// degrees.
+pub fn cardinal(bearing: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((bearing / 45.0).round() as usize) % POINTS.len()]
+}
+
+/// Returns the length, in km, of the path through a trip's stops, skipping stops without
+/// coordinates, or `None` if fewer than two of its stops have coordinates.
+pub fn trip_length(trip: &Trip, metric: Metric) -> Option<f64> {
+    use itertools::Itertools;
+
+    let points = trip
+        .stop_times
         .iter()
-        .flat_map(|p1| {
-            points
-                .iter()
-                .map(|p2| p1.geodesic_distance(p2) / 1000.0)
-                .reduce(f64::max)
-        })
-        .fold((f64::INFINITY, 0.0), |(min, max), dist| {
+        .filter_map(|st| stop_point(&st.stop))
+        .collect::<Vec<_>>();
+    (points.len() >= 2).then(|| {
+        points
+            .iter()
+            .tuple_windows()
+            .map(|(p1, p2)| distance(p1, p2, metric))
+            .sum()
+    })
+}
+
+/// Returns the cell of a grid of squares `cell_size` km across containing a point, so that points
+/// within `cell_size` of each other are always in the same or neighboring cells.
+///
+/// The grid is an equirectangular projection, which is accurate enough for this away from the
+/// poles.
+pub fn grid_cell(point: &Point, cell_size: f64) -> (i64, i64) {
+    // km per degree of latitude
+    const KM_PER_DEGREE: f64 = 111.195;
+
+    let cell_size = cell_size.max(1e-6);
+    let y = point.y() * KM_PER_DEGREE;
+    let x = point.x() * KM_PER_DEGREE * point.y().to_radians().cos();
+    (
+        (x / cell_size).floor() as i64,
+        (y / cell_size).floor() as i64,
+    )
+}
+
+pub fn radius_and_diameter(points: &[Point], metric: Metric) -> (f64, f64) {
+    let (radius, diameter, _) = radius_diameter_average(points, metric);
+    (radius, diameter)
+}
+
+/// Returns the radius and diameter of a set of points, like [`radius_and_diameter`], along with
+/// the average over the points of the distance to the furthest point from each.
+pub fn radius_diameter_average(points: &[Point], metric: Metric) -> (f64, f64, f64) {
+    // the distance from each point to the furthest point from it. distances are symmetric, so
+    // each pair of points is only measured once
+    let mut furthest = vec![0.0_f64; points.len()];
+    for (i, p1) in points.iter().enumerate() {
+        for (j, p2) in points.iter().enumerate().skip(i + 1) {
+            let dist = distance(p1, p2, metric);
+            furthest[i] = furthest[i].max(dist);
+            furthest[j] = furthest[j].max(dist);
+        }
+    }
+    let average = furthest.iter().sum::<f64>() / furthest.len().max(1) as f64;
+    let (radius, diameter) = furthest
+        .into_iter()
+        .fold((f64::INFINITY, 0.0_f64), |(min, max), dist| {
             (min.min(dist), max.max(dist))
-        })
+        });
+    (radius, diameter, average)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linear congruential generator, for reproducible random points.
+    struct Lcg(u64);
+
+    impl Lcg {
+        /// Returns a number uniformly distributed in `[0, 1)`.
+        fn next_f64(&mut self) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) as f64 / (1u64 << 31) as f64
+        }
+    }
+
+    /// Measures the distance from every point to every other point, as in the module docs.
+    fn naive_radius_and_diameter(points: &[Point], metric: Metric) -> (f64, f64) {
+        points
+            .iter()
+            .map(|p1| {
+                points
+                    .iter()
+                    .map(|p2| distance(p1, p2, metric))
+                    .fold(0.0, f64::max)
+            })
+            .fold((f64::INFINITY, 0.0), |(min, max), dist| {
+                (min.min(dist), max.max(dist))
+            })
+    }
+
+    #[test]
+    fn rhumb_is_longer_than_geodesic() {
+        // due east for over 3000 km, far from the equator
+        let (west, east) = (Point::new(0.0, 60.0), Point::new(60.0, 60.0));
+        let geodesic = distance(&west, &east, Metric::Geodesic);
+        let rhumb = distance(&west, &east, Metric::Rhumb);
+        assert!(rhumb >= geodesic);
+        // noticeably so, at this distance
+        assert!(rhumb - geodesic > 50.0, "{rhumb} km vs {geodesic} km");
+    }
+
+    #[test]
+    fn radius_and_diameter_matches_naive() {
+        let mut rng = Lcg(1);
+        for len in [1, 2, 3, 10, 50] {
+            let points = (0..len)
+                .map(|_| Point::new(-122.5 + 0.3 * rng.next_f64(), 37.6 + 0.2 * rng.next_f64()))
+                .collect::<Vec<_>>();
+            for metric in [Metric::Geodesic, Metric::Haversine] {
+                let (radius, diameter) = radius_and_diameter(&points, metric);
+                let (naive_radius, naive_diameter) = naive_radius_and_diameter(&points, metric);
+                // the distance from `a` to `b` may differ from `b` to `a` in the last place
+                assert!(
+                    (radius - naive_radius).abs() < 1e-9,
+                    "{len} points, {metric:?}"
+                );
+                assert!(
+                    (diameter - naive_diameter).abs() < 1e-9,
+                    "{len} points, {metric:?}"
+                );
+            }
+        }
+    }
 }
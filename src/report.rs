@@ -0,0 +1,74 @@
+//! Run metadata for `--report-json`.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Initializes `pretty_env_logger`, wrapped so that warnings and errors are counted even when
+/// they are filtered out of the log output.
+pub fn init_logger() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let inner = builder.build();
+    log::set_max_level(inner.filter().max(LevelFilter::Warn));
+    log::set_boxed_logger(Box::new(CountingLogger { inner })).expect("logger already initialized");
+}
+
+struct CountingLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> Log for CountingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Warn {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug)]
+pub struct Report {
+    start: Instant,
+    pub routes: usize,
+    pub trips: usize,
+}
+
+impl Report {
+    pub fn start() -> Self {
+        Report {
+            start: Instant::now(),
+            routes: 0,
+            trips: 0,
+        }
+    }
+
+    /// Prints the report as a single line of JSON to stderr.
+    pub fn emit(&self, success: bool) {
+        let summary = serde_json::json!({
+            "success": success,
+            "routes": self.routes,
+            "trips": self.trips,
+            "warnings": WARNINGS.load(Ordering::Relaxed),
+            "elapsed_ms": self.start.elapsed().as_millis() as u64,
+        });
+        eprintln!("{summary}");
+    }
+}
@@ -0,0 +1,89 @@
+//! Route geometry from `shapes.txt`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use geo::{LineLocatePoint, LineString};
+use gtfs_structures::{Gtfs, Stop};
+use itertools::Itertools;
+
+use crate::{multimap::MultiMap, radius, types::RouteDir};
+
+/// Returns the shape with the given id as a line string, with its points in sequence order.
+pub fn shape_line(gtfs: &Gtfs, shape_id: &str) -> Option<LineString> {
+    let points = gtfs.shapes.get(shape_id)?;
+    Some(
+        points
+            .iter()
+            .sorted_by_key(|point| point.sequence)
+            .map(|point| (point.longitude, point.latitude))
+            .collect(),
+    )
+}
+
+/// Returns the longest of the shapes with the given ids, as a line string.
+pub fn longest_shape<'a>(
+    gtfs: &Gtfs,
+    shape_ids: impl IntoIterator<Item = &'a str>,
+    metric: radius::Metric,
+) -> Option<LineString> {
+    let length = |line: &LineString| {
+        line.points()
+            .tuple_windows()
+            .map(|(p1, p2)| radius::distance(&p1, &p2, metric))
+            .sum::<f64>()
+    };
+    shape_ids
+        .into_iter()
+        .unique()
+        .filter_map(|shape_id| shape_line(gtfs, shape_id))
+        .max_by(|a, b| length(a).total_cmp(&length(b)))
+}
+
+/// Reorders each route/direction's stops by their position along the shape used by the most
+/// trips on that route/direction.
+///
+/// Route/directions with no shape, or with stops missing coordinates, keep their existing order.
+pub fn order_stops_by_shape(
+    stops_by_route: &mut MultiMap<RouteDir, Arc<Stop>>,
+    gtfs: &Gtfs,
+    args: &crate::Args,
+) {
+    let mut shape_counts = BTreeMap::<_, HashMap<_, usize>>::new();
+    for trip in gtfs.trips.values() {
+        if let Some(shape_id) = &trip.shape_id {
+            *shape_counts
+                .entry(RouteDir::from_trip(trip, args))
+                .or_default()
+                .entry(shape_id.as_str())
+                .or_default() += 1;
+        }
+    }
+
+    for (route, stops) in stops_by_route.iter_mut() {
+        let shape = shape_counts
+            .get(route)
+            .and_then(|counts| counts.iter().max_by_key(|&(id, count)| (count, *id)))
+            .and_then(|(shape_id, _)| shape_line(gtfs, shape_id));
+        let Some(shape) = shape else {
+            log::debug!("no shape for {route:?}; keeping merged order");
+            continue;
+        };
+        let positions = stops
+            .iter()
+            .map(|stop| shape.line_locate_point(&radius::stop_point(stop)?))
+            .collect::<Option<Vec<_>>>();
+        let Some(positions) = positions else {
+            log::warn!("stops without coordinates in {route:?}; keeping merged order");
+            continue;
+        };
+        let mut indexed = positions
+            .into_iter()
+            .zip(stops.drain(..))
+            .collect::<Vec<_>>();
+        indexed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        stops.extend(indexed.into_iter().map(|(_, stop)| stop));
+    }
+}
@@ -1,11 +1,18 @@
+// `Table` is a general-purpose container, and not every accessor has a caller in every command.
+#![allow(dead_code)]
+
 use std::fmt;
 
+use crate::output::{csv_field, html_escape};
+
 /// A table, arranged in column-major order.
 #[derive(Debug)]
 pub struct Table<C, R, T> {
     col_headers: Vec<C>,
     row_headers: Vec<R>,
     data: Vec<T>,
+    /// An extra row, shown after the others, summarizing each column.
+    summary: Option<(R, Vec<T>)>,
 }
 
 impl<C, R, T> Table<C, R, T> {
@@ -14,25 +21,18 @@ impl<C, R, T> Table<C, R, T> {
             col_headers: Vec::new(),
             row_headers,
             data: Vec::new(),
+            summary: None,
         }
     }
 
-    /// Adds a new column, returning a reference to the new column.
-    pub fn add_column(&mut self, header: C, default: T) -> &mut [T]
+    /// Adds a new column, checking that it has one cell for each row.
+    pub fn push_column(&mut self, header: C, mut column: Vec<T>) -> anyhow::Result<()>
     where
-        T: Clone,
+        C: fmt::Debug,
     {
-        self.col_headers.push(header);
-        let start = self.data.len();
-        let end = start + self.row_headers.len();
-        self.data.resize(end, default);
-        &mut self.data[start..end]
-    }
-
-    pub fn push_column(&mut self, header: C, mut column: Vec<T>) -> anyhow::Result<()> {
         if column.len() != self.row_headers.len() {
             Err(anyhow::anyhow!(
-                "Incorrect size of column (got {}, expected {})",
+                "Incorrect size of column {header:?} (got {}, expected {})",
                 column.len(),
                 self.row_headers.len()
             ))
@@ -43,6 +43,32 @@ impl<C, R, T> Table<C, R, T> {
         }
     }
 
+    /// Adds a summary row, shown in bold after the other rows, with a cell for each column
+    /// computed from that column's cells.
+    ///
+    /// This should be called after all columns have been added, since later columns won't have a
+    /// summary cell.
+    pub fn with_summary_row(mut self, label: R, f: impl Fn(&[T]) -> T) -> Self {
+        let rows = self.row_headers.len();
+        let cells = (0..self.col_headers.len())
+            .map(|i| f(&self.data[i * rows..(i + 1) * rows]))
+            .collect();
+        self.summary = Some((label, cells));
+        self
+    }
+
+    /// Returns the summary row's header and cells, if it has one.
+    pub fn summary_row(&self) -> Option<(&R, &[T])> {
+        self.summary
+            .as_ref()
+            .map(|(label, cells)| (label, cells.as_slice()))
+    }
+
+    /// Returns `true` if the table has no rows or no columns.
+    pub fn is_empty(&self) -> bool {
+        self.col_headers.is_empty() || self.row_headers.is_empty()
+    }
+
     pub fn col_headers(&self) -> &[C] {
         &self.col_headers
     }
@@ -51,6 +77,61 @@ impl<C, R, T> Table<C, R, T> {
         &self.row_headers
     }
 
+    pub fn col_headers_mut(&mut self) -> &mut [C] {
+        &mut self.col_headers
+    }
+
+    pub fn row_headers_mut(&mut self) -> &mut [R] {
+        &mut self.row_headers
+    }
+
+    /// Returns the first column whose header is equal to `header`.
+    pub fn column_by_header(&self, header: &C) -> Option<&[T]>
+    where
+        C: PartialEq,
+    {
+        let idx = self.col_headers.iter().position(|h| h == header)?;
+        let rows = self.row_headers.len();
+        Some(&self.data[idx * rows..(idx + 1) * rows])
+    }
+
+    /// Returns the cell in the first row whose header is equal to `row`, and the first column
+    /// whose header is equal to `col`.
+    pub fn get_by_headers(&self, row: &R, col: &C) -> Option<&T>
+    where
+        R: PartialEq,
+        C: PartialEq,
+    {
+        let row = self.row_headers.iter().position(|h| h == row)?;
+        self.column_by_header(col).map(|column| &column[row])
+    }
+
+    /// Swaps the rows and columns of the table.
+    ///
+    /// A summary row becomes the last column.
+    pub fn transpose(self) -> Table<R, C, T> {
+        let rows = self.row_headers.len();
+        let cols = self.col_headers.len();
+        let mut data = self
+            .data
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| ((i % rows) * cols + i / rows, cell))
+            .collect::<Vec<_>>();
+        data.sort_unstable_by_key(|&(i, _)| i);
+        let mut table = Table {
+            col_headers: self.row_headers,
+            row_headers: self.col_headers,
+            data: data.into_iter().map(|(_, cell)| cell).collect(),
+            summary: None,
+        };
+        if let Some((label, mut cells)) = self.summary {
+            table.col_headers.push(label);
+            table.data.append(&mut cells);
+        }
+        table
+    }
+
     /// Produces an iterator of iterators, in row-major order.
     pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
         RowsIter {
@@ -60,12 +141,13 @@ impl<C, R, T> Table<C, R, T> {
         }
     }
 
-    pub fn formatter<'a, Cf, Rf, Tf, Cs, Rs, Ts>(
+    pub fn formatter_with<'a, Cf, Rf, Tf, Cs, Rs, Ts>(
         &'a self,
+        format: Format,
         col_fmt: Cf,
         row_fmt: Rf,
         data_fmt: Tf,
-        align: Align,
+        align: impl Into<Aligns>,
     ) -> impl fmt::Display + 'a
     where
         Cf: Fn(&'a C) -> Cs + 'a,
@@ -76,10 +158,11 @@ impl<C, R, T> Table<C, R, T> {
         Ts: fmt::Display + 'a,
     {
         TableFormatter {
+            format,
             col_fmt,
             row_fmt,
             data_fmt,
-            align,
+            align: align.into(),
             table: self,
         }
     }
@@ -106,14 +189,15 @@ impl<'a, T> Iterator for RowsIter<'a, T> {
 }
 
 struct TableFormatter<'a, C, R, T, Cf, Rf, Tf> {
+    format: Format,
     col_fmt: Cf,
     row_fmt: Rf,
     data_fmt: Tf,
-    align: Align,
+    align: Aligns,
     table: &'a Table<C, R, T>,
 }
 
-impl<'a, C, R, T, Cf, Rf, Tf, Cs, Rs, Ts> fmt::Display for TableFormatter<'a, C, R, T, Cf, Rf, Tf>
+impl<'a, C, R, T, Cf, Rf, Tf, Cs, Rs, Ts> TableFormatter<'a, C, R, T, Cf, Rf, Tf>
 where
     Cf: Fn(&'a C) -> Cs,
     Rf: Fn(&'a R) -> Rs,
@@ -122,17 +206,20 @@ where
     Rs: fmt::Display + 'a,
     Ts: fmt::Display + 'a,
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt_markdown(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "—")?;
         for header in self.table.col_headers() {
             write!(f, "| {}", (self.col_fmt)(header))?;
         }
         writeln!(f)?;
 
-        write!(f, "---")?;
-        let divider = self.align.divider();
-        for _ in self.table.col_headers() {
-            f.write_str(divider)?;
+        write!(
+            f,
+            "{}",
+            self.align.row_headers.map_or("---", Align::divider)
+        )?;
+        for i in 0..self.table.col_headers().len() {
+            write!(f, "|{}", self.align.column(i).divider())?;
         }
         writeln!(f)?;
 
@@ -143,8 +230,140 @@ where
             }
             writeln!(f)?;
         }
+
+        if let Some((label, cells)) = self.table.summary_row() {
+            write!(f, "**{}**", (self.row_fmt)(label))?;
+            for cell in cells {
+                let cell = (self.data_fmt)(cell).to_string();
+                if cell.is_empty() {
+                    write!(f, " | ")?;
+                } else {
+                    write!(f, " | **{cell}**")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_csv(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for header in self.table.col_headers() {
+            write!(f, ",{}", csv_field(&(self.col_fmt)(header).to_string()))?;
+        }
+        writeln!(f)?;
+
+        for (header, row) in self.table.row_headers().iter().zip(self.table.rows()) {
+            write!(f, "{}", csv_field(&(self.row_fmt)(header).to_string()))?;
+            for cell in row {
+                write!(f, ",{}", csv_field(&(self.data_fmt)(cell).to_string()))?;
+            }
+            writeln!(f)?;
+        }
+
+        if let Some((label, cells)) = self.table.summary_row() {
+            write!(f, "{}", csv_field(&(self.row_fmt)(label).to_string()))?;
+            for cell in cells {
+                write!(f, ",{}", csv_field(&(self.data_fmt)(cell).to_string()))?;
+            }
+            writeln!(f)?;
+        }
         Ok(())
     }
+
+    fn fmt_html(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row_header_style = self.align.row_headers.map_or("", Align::style);
+        writeln!(f, "<table>")?;
+        write!(f, "<thead><tr><th></th>")?;
+        for (i, header) in self.table.col_headers().iter().enumerate() {
+            write!(
+                f,
+                "<th{}>{}</th>",
+                self.align.column(i).style(),
+                html_escape(&(self.col_fmt)(header).to_string())
+            )?;
+        }
+        writeln!(f, "</tr></thead>")?;
+
+        writeln!(f, "<tbody>")?;
+        for (header, row) in self.table.row_headers().iter().zip(self.table.rows()) {
+            write!(
+                f,
+                "<tr><th{row_header_style}>{}</th>",
+                html_escape(&(self.row_fmt)(header).to_string())
+            )?;
+            for (i, cell) in row.enumerate() {
+                write!(
+                    f,
+                    "<td{}>{}</td>",
+                    self.align.column(i).style(),
+                    html_escape(&(self.data_fmt)(cell).to_string())
+                )?;
+            }
+            writeln!(f, "</tr>")?;
+        }
+        writeln!(f, "</tbody>")?;
+
+        if let Some((label, cells)) = self.table.summary_row() {
+            write!(
+                f,
+                "<tfoot><tr><th{row_header_style}>{}</th>",
+                html_escape(&(self.row_fmt)(label).to_string())
+            )?;
+            for (i, cell) in cells.iter().enumerate() {
+                write!(
+                    f,
+                    "<th{}>{}</th>",
+                    self.align.column(i).style(),
+                    html_escape(&(self.data_fmt)(cell).to_string())
+                )?;
+            }
+            writeln!(f, "</tr></tfoot>")?;
+        }
+        write!(f, "</table>")
+    }
+}
+
+impl<'a, C, R, T, Cf, Rf, Tf, Cs, Rs, Ts> fmt::Display for TableFormatter<'a, C, R, T, Cf, Rf, Tf>
+where
+    Cf: Fn(&'a C) -> Cs,
+    Rf: Fn(&'a R) -> Rs,
+    Tf: Fn(&'a T) -> Ts,
+    Cs: fmt::Display + 'a,
+    Rs: fmt::Display + 'a,
+    Ts: fmt::Display + 'a,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            Format::Markdown => self.fmt_markdown(f),
+            Format::Csv => self.fmt_csv(f),
+            Format::Html => self.fmt_html(f),
+            Format::Json => unreachable!("JSON output is only supported by route-summary"),
+        }
+    }
+}
+
+/// How to render tables and other output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    #[value(alias = "md")]
+    Markdown,
+    Csv,
+    Html,
+    /// Only supported by `route-summary`, which prints its own JSON rather than tables.
+    Json,
+}
+
+impl Format {
+    /// The file extension for output in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Csv => "csv",
+            Format::Html => "html",
+            Format::Json => "json",
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -157,9 +376,137 @@ pub enum Align {
 impl Align {
     fn divider(self) -> &'static str {
         match self {
-            Align::Left => "|:--",
-            Align::Center => "|:-:",
-            Align::Right => "|--:",
+            Align::Left => ":--",
+            Align::Center => ":-:",
+            Align::Right => "--:",
+        }
+    }
+
+    fn style(self) -> &'static str {
+        match self {
+            Align::Left => r#" style="text-align: left""#,
+            Align::Center => r#" style="text-align: center""#,
+            Align::Right => r#" style="text-align: right""#,
+        }
+    }
+}
+
+/// The alignment of each column of a table.
+#[derive(Clone, Debug)]
+pub struct Aligns {
+    default: Align,
+    row_headers: Option<Align>,
+    columns: Vec<Align>,
+}
+
+impl Aligns {
+    /// Aligns all data columns the same way, leaving the row header column unaligned.
+    pub fn new(default: Align) -> Self {
+        Aligns {
+            default,
+            row_headers: None,
+            columns: Vec::new(),
         }
     }
+
+    /// Sets the alignment of the row header column.
+    pub fn with_row_headers(mut self, align: Align) -> Self {
+        self.row_headers = Some(align);
+        self
+    }
+
+    /// Sets the alignments of the first data columns, in order. Any further columns use the
+    /// default alignment.
+    pub fn with_columns(mut self, columns: Vec<Align>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    fn column(&self, idx: usize) -> Align {
+        self.columns.get(idx).copied().unwrap_or(self.default)
+    }
+}
+
+impl From<Align> for Aligns {
+    fn from(default: Align) -> Self {
+        Aligns::new(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A table with rows `a` and `b`, and columns `x` and `y`, whose cells are their row and
+    /// column headers.
+    fn table() -> Table<&'static str, &'static str, String> {
+        let mut table = Table::new(vec!["a", "b"]);
+        table
+            .push_column("x", vec!["ax".to_owned(), "bx".to_owned()])
+            .unwrap();
+        table
+            .push_column("y", vec!["ay".to_owned(), "by".to_owned()])
+            .unwrap();
+        table
+    }
+
+    #[test]
+    fn column_by_header() {
+        let table = table();
+        assert_eq!(table.column_by_header(&"x").unwrap(), ["ax", "bx"]);
+        assert_eq!(table.column_by_header(&"y").unwrap(), ["ay", "by"]);
+        assert_eq!(table.column_by_header(&"z"), None);
+    }
+
+    #[test]
+    fn get_by_headers() {
+        let table = table();
+        assert_eq!(table.get_by_headers(&"a", &"x").unwrap(), "ax");
+        assert_eq!(table.get_by_headers(&"b", &"y").unwrap(), "by");
+        // missing row
+        assert_eq!(table.get_by_headers(&"c", &"x"), None);
+        // missing column
+        assert_eq!(table.get_by_headers(&"a", &"z"), None);
+    }
+
+    #[test]
+    fn transpose_round_trips() {
+        let table = table().transpose();
+        assert_eq!(table.col_headers, ["a", "b"]);
+        assert_eq!(table.row_headers, ["x", "y"]);
+        assert_eq!(table.data, ["ax", "ay", "bx", "by"]);
+
+        let table = table.transpose();
+        assert_eq!(table.col_headers, ["x", "y"]);
+        assert_eq!(table.row_headers, ["a", "b"]);
+        assert_eq!(table.data, ["ax", "bx", "ay", "by"]);
+    }
+
+    #[test]
+    fn transpose_moves_summary_row_to_last_column() {
+        let table = table()
+            .with_summary_row("total", |column| column.concat())
+            .transpose();
+        assert_eq!(table.col_headers, ["a", "b", "total"]);
+        assert_eq!(table.data, ["ax", "ay", "bx", "by", "axbx", "ayby"]);
+        assert!(table.summary_row().is_none());
+
+        // and back, as an ordinary row
+        let table = table.transpose();
+        assert_eq!(table.row_headers, ["a", "b", "total"]);
+        assert_eq!(table.data, ["ax", "bx", "axbx", "ay", "by", "ayby"]);
+    }
+
+    #[test]
+    fn mutated_headers_are_formatted() {
+        let mut table = table();
+        table.row_headers_mut()[0] = "renamed row";
+        table.col_headers_mut()[1] = "renamed column";
+        let markdown = table
+            .formatter_with(Format::Markdown, |h| h, |r| r, |c| c, Align::Left)
+            .to_string();
+        assert!(markdown.contains("**renamed row** | ax | ay"));
+        assert!(markdown.contains("| renamed column"));
+        assert!(!markdown.contains("**a**"));
+    }
 }
@@ -0,0 +1,110 @@
+//! Finds potential transfers between routes.
+
+use std::collections::{BTreeMap, HashMap};
+
+use gtfs_structures::{Gtfs, Stop, TransferType};
+use itertools::Itertools;
+
+use crate::radius::{self, Metric};
+
+/// The closest pair of stops on two different routes.
+#[derive(Debug)]
+pub struct NearbyStops<'a> {
+    pub routes: (&'a str, &'a str),
+    pub stops: (&'a Stop, &'a Stop),
+    /// The distance between the stops, in km.
+    pub distance: f64,
+}
+
+/// Collects the distinct stops served by each route, ignoring direction.
+pub fn stops_by_route_id(gtfs: &Gtfs) -> BTreeMap<&str, Vec<&Stop>> {
+    let mut stops_by_route = BTreeMap::<_, Vec<_>>::new();
+    for trip in gtfs.trips.values() {
+        stops_by_route
+            .entry(trip.route_id.as_str())
+            .or_default()
+            .extend(trip.stop_times.iter().map(|st| st.stop.as_ref()));
+    }
+    for stops in stops_by_route.values_mut() {
+        *stops = stops.drain(..).unique_by(|stop| &stop.id).collect();
+    }
+    stops_by_route
+}
+
+/// Finds, for each pair of routes with stops within `threshold` km of each other, the closest
+/// such pair of stops. A stop served by both routes is at distance zero.
+///
+/// Pairs are ordered by route id. Stops without coordinates are ignored.
+pub fn nearby_stops<'a>(
+    stops_by_route: &BTreeMap<&'a str, Vec<&'a Stop>>,
+    threshold: f64,
+    metric: Metric,
+) -> Vec<NearbyStops<'a>> {
+    let route_ids = stops_by_route.keys().copied().collect::<Vec<_>>();
+    let stops = stops_by_route
+        .values()
+        .enumerate()
+        .flat_map(|(route, stops)| {
+            stops
+                .iter()
+                .filter_map(move |&stop| Some((route, radius::stop_point(stop)?, stop)))
+        })
+        .collect::<Vec<_>>();
+
+    // bucket stops into a grid, so that each stop only needs to be compared with the stops in its
+    // own and neighboring cells
+    let mut grid = HashMap::<_, Vec<usize>>::new();
+    for (i, (_, point, _)) in stops.iter().enumerate() {
+        grid.entry(radius::grid_cell(point, threshold))
+            .or_default()
+            .push(i);
+    }
+
+    // the closest pair of stops for each pair of routes, by index
+    let mut closest = BTreeMap::<(usize, usize), (f64, usize, usize)>::new();
+    for (i, (route, point, _)) in stops.iter().enumerate() {
+        let (x, y) = radius::grid_cell(point, threshold);
+        let neighbors = (x - 1..=x + 1)
+            .cartesian_product(y - 1..=y + 1)
+            .filter_map(|cell| grid.get(&cell))
+            .flatten();
+        for &j in neighbors {
+            let (other_route, other_point, _) = &stops[j];
+            // only compare each pair of routes one way round
+            if other_route <= route {
+                continue;
+            }
+            let distance = radius::distance(point, other_point, metric);
+            if distance > threshold {
+                continue;
+            }
+            closest
+                .entry((*route, *other_route))
+                .and_modify(|best| {
+                    if distance < best.0 {
+                        *best = (distance, i, j);
+                    }
+                })
+                .or_insert((distance, i, j));
+        }
+    }
+
+    closest
+        .into_iter()
+        .map(|((a, b), (distance, i, j))| NearbyStops {
+            routes: (route_ids[a], route_ids[b]),
+            stops: (stops[i].2, stops[j].2),
+            distance,
+        })
+        .collect()
+}
+
+/// Returns a description of a `transfers.txt` transfer type.
+pub fn transfer_type_label(transfer_type: TransferType) -> &'static str {
+    match transfer_type {
+        TransferType::Recommended => "recommended",
+        TransferType::Timed => "timed",
+        TransferType::MinTime => "minimum time",
+        TransferType::Impossible => "not possible",
+    }
+}
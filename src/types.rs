@@ -1,9 +1,13 @@
-use gtfs_structures::{DirectionType, Route, Trip};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use gtfs_structures::{DirectionType, Route, Stop, StopTime, Trip};
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct RouteDir {
     pub route_id: Option<String>,
     pub direction: Direction,
+    /// The trips' headsign, if grouping by `--group-by-headsign`.
+    pub headsign: Option<String>,
 }
 
 impl RouteDir {
@@ -15,31 +19,141 @@ impl RouteDir {
         };
         RouteDir {
             route_id,
-            direction: Direction::from_trip(trip, args.direction_from_trip_name),
+            direction: Direction::from_trip(trip, args),
+            headsign: if args.group_by_headsign {
+                trip.trip_headsign.clone()
+            } else {
+                None
+            },
         }
     }
 
     pub fn format(
         &self,
-        use_short_name: bool,
+        args: &crate::Args,
         routes: &std::collections::HashMap<String, Route>,
     ) -> String {
+        let direction = match &self.headsign {
+            Some(headsign) => format!("{:?} to {headsign}", self.direction),
+            None => format!("{:?}", self.direction),
+        };
         if let Some(route_id) = &self.route_id {
-            let route_name = routes
-                .get(route_id)
-                .map(|r| {
-                    if use_short_name {
-                        &r.short_name
-                    } else {
-                        &r.long_name
-                    }
-                })
-                .filter(|name| !name.is_empty())
-                .unwrap_or(route_id);
-            format!("{route_name} ({:?})", self.direction)
+            let route = routes.get(route_id);
+            let route_name = format_route(route_id, args, routes);
+            let desc = route
+                .filter(|_| args.show_desc)
+                .and_then(|r| r.desc.as_deref())
+                .map(str::trim)
+                .filter(|desc| !desc.is_empty());
+            if let Some(desc) = desc {
+                let desc = truncate(desc, args.desc_max_len);
+                format!("{route_name} ({direction}) — {desc}")
+            } else {
+                format!("{route_name} ({direction})")
+            }
         } else {
-            format!("{:?}", self.direction)
+            direction
+        }
+    }
+}
+
+/// Returns the short or long name of a route, as selected by `--use-short-name`, falling back to
+/// the route id if the name is empty. A name given in `--names` takes precedence.
+pub fn format_route<'a>(
+    route_id: &'a str,
+    args: &'a crate::Args,
+    routes: &'a std::collections::HashMap<String, Route>,
+) -> &'a str {
+    if let Some(name) = args.name_overrides.routes.get(route_id) {
+        return name;
+    }
+    routes
+        .get(route_id)
+        .map(|r| {
+            if args.use_short_name {
+                &r.short_name
+            } else {
+                &r.long_name
+            }
+        })
+        .filter(|name| !name.is_empty())
+        .map_or(route_id, String::as_str)
+}
+
+/// Formats a stop's name (or its name from `--names`) for display, followed by its `stop_code`
+/// if `--show-stop-code` is set and the stop has one.
+pub fn format_stop<'a>(stop: &'a Stop, args: &'a crate::Args) -> std::borrow::Cow<'a, str> {
+    use std::borrow::Cow;
+
+    let name = args
+        .name_overrides
+        .stops
+        .get(&stop.id)
+        .unwrap_or(&stop.name);
+    match &stop.code {
+        Some(code) if args.show_stop_code && !code.is_empty() => {
+            Cow::Owned(format!("{name} ({code})"))
         }
+        _ => Cow::Borrowed(name),
+    }
+}
+
+/// Display names to use in place of the feed's names, loaded from the `--names` file.
+///
+/// The file is TOML, with tables mapping ids to names:
+///
+/// ```toml
+/// [routes]
+/// R1 = "Red Line"
+///
+/// [stops]
+/// S1 = "Main Street"
+/// ```
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct NameOverrides {
+    #[serde(default)]
+    pub routes: HashMap<String, String>,
+    #[serde(default)]
+    pub stops: HashMap<String, String>,
+}
+
+impl NameOverrides {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|err| anyhow::anyhow!("invalid names file {}: {err}", path.display()))
+    }
+}
+
+/// Returns whether `a` and `b` are the same stop.
+///
+/// Stops are normally compared by pointer, which is cheap, but fails if the same stop is loaded
+/// into more than one `Arc`. With `by_id`, stops with different pointers are compared by
+/// `stop_id` instead, at the cost of a string comparison for every mismatch.
+pub fn stops_equal(a: &Arc<Stop>, b: &Arc<Stop>, by_id: bool) -> bool {
+    Arc::ptr_eq(a, b) || (by_id && a.id == b.id)
+}
+
+/// Returns the trip's first stop time at `stop`, comparing stops as in [`stops_equal`].
+pub fn stop_time_at<'a>(trip: &'a Trip, stop: &Arc<Stop>, by_id: bool) -> Option<&'a StopTime> {
+    trip.stop_times
+        .iter()
+        .find(|st| stops_equal(&st.stop, stop, by_id))
+}
+
+/// Truncates `s` to at most `max_len` characters, marking the cut with an ellipsis.
+fn truncate(s: &str, max_len: usize) -> std::borrow::Cow<'_, str> {
+    use std::borrow::Cow;
+
+    if s.chars().count() <= max_len {
+        Cow::Borrowed(s)
+    } else {
+        let mut truncated = s
+            .chars()
+            .take(max_len.saturating_sub(1))
+            .collect::<String>();
+        truncated.push('…');
+        Cow::Owned(truncated)
     }
 }
 
@@ -51,14 +165,43 @@ pub enum Direction {
 }
 
 impl Direction {
-    pub fn from_trip(trip: &Trip, direction_from_trip_name: bool) -> Self {
-        if direction_from_trip_name {
+    pub fn from_trip(trip: &Trip, args: &crate::Args) -> Self {
+        let direction: Direction = if let Some(regex) = &args.direction_regex {
+            trip.trip_short_name
+                .as_deref()
+                .and_then(|name| regex.captures(name)?.get(1))
+                .and_then(|code| {
+                    let code = code.as_str();
+                    if args.inbound_codes.iter().any(|c| c == code) {
+                        Some(Direction::Inbound)
+                    } else if args.outbound_codes.iter().any(|c| c == code) {
+                        Some(Direction::Outbound)
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or_else(|| trip.direction_id.into())
+        } else if args.direction_from_trip_name {
             trip.trip_short_name
                 .as_ref()
                 .and_then(|name| name.parse::<u32>().ok())
                 .into()
         } else {
             trip.direction_id.into()
+        };
+        if args.reverse_directions {
+            direction.reverse()
+        } else {
+            direction
+        }
+    }
+
+    /// Swaps inbound and outbound.
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::None => Direction::None,
+            Direction::Inbound => Direction::Outbound,
+            Direction::Outbound => Direction::Inbound,
         }
     }
 }
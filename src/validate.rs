@@ -0,0 +1,269 @@
+//! Data-quality checks for GTFS feeds.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek},
+};
+
+use gtfs_structures::{Gtfs, Stop};
+use itertools::Itertools;
+
+use crate::{radius, types::RouteDir};
+
+#[derive(Debug)]
+pub struct StopTimeIssue<'a> {
+    pub trip_id: &'a str,
+    pub stop_sequence: u16,
+    pub kind: StopTimeIssueKind,
+}
+
+#[derive(Debug)]
+pub enum StopTimeIssueKind {
+    /// The vehicle departs a stop before it arrives.
+    NegativeDwell { arrival: u32, departure: u32 },
+    /// The vehicle arrives at a stop before it departs the previous stop.
+    Decreasing { previous: u32, current: u32 },
+}
+
+/// Finds stop times with negative dwell, or whose times decrease relative to the previous stop.
+///
+/// Times are seconds since the start of the service day, so times past 24:00:00 compare
+/// correctly with the earlier times on the same trip.
+pub fn check_stop_times(gtfs: &Gtfs) -> Vec<StopTimeIssue<'_>> {
+    let mut issues = Vec::new();
+    for trip in gtfs.trips.values().sorted_by_key(|trip| &trip.id) {
+        let mut previous = None;
+        for stop_time in &trip.stop_times {
+            let mut issue = |kind| {
+                issues.push(StopTimeIssue {
+                    trip_id: &trip.id,
+                    stop_sequence: stop_time.stop_sequence,
+                    kind,
+                })
+            };
+            if let (Some(arrival), Some(departure)) =
+                (stop_time.arrival_time, stop_time.departure_time)
+            {
+                if arrival > departure {
+                    issue(StopTimeIssueKind::NegativeDwell { arrival, departure });
+                }
+            }
+            if let (Some(previous), Some(current)) = (
+                previous,
+                stop_time.arrival_time.or(stop_time.departure_time),
+            ) {
+                if current < previous {
+                    issue(StopTimeIssueKind::Decreasing { previous, current });
+                }
+            }
+            if let Some(time) = stop_time.departure_time.or(stop_time.arrival_time) {
+                previous = Some(time);
+            }
+        }
+    }
+    issues
+}
+
+/// Counts, for each route/direction pair, the stop times with neither an arrival nor a departure
+/// time, which have to be interpolated, and the total number of stop times.
+pub fn count_untimed_stop_times(
+    gtfs: &Gtfs,
+    args: &crate::Args,
+) -> BTreeMap<RouteDir, (usize, usize)> {
+    let mut counts = BTreeMap::<_, (usize, usize)>::new();
+    for trip in gtfs.trips.values() {
+        let (untimed, total) = counts.entry(RouteDir::from_trip(trip, args)).or_default();
+        *untimed += trip
+            .stop_times
+            .iter()
+            .filter(|st| st.arrival_time.is_none() && st.departure_time.is_none())
+            .count();
+        *total += trip.stop_times.len();
+    }
+    counts
+}
+
+/// Finds groups of distinct stops within `threshold` km of each other, which may be accidental
+/// duplicates. Groups are ordered largest first, and the stops in each group by id.
+///
+/// A stop and its parent station are expected to share a location, so they aren't counted as
+/// duplicates of each other.
+pub fn find_duplicate_locations(gtfs: &Gtfs, threshold: f64) -> Vec<Vec<&Stop>> {
+    let stops = gtfs
+        .stops
+        .values()
+        .filter_map(|stop| Some((radius::stop_point(stop)?, stop.as_ref())))
+        .sorted_by_key(|(_, stop)| &stop.id)
+        .collect::<Vec<_>>();
+
+    // bucket stops into a grid of cells `threshold` km across, so that each stop only needs to be
+    // compared with the stops in its own and neighboring cells
+    let cell = |point| radius::grid_cell(point, threshold);
+    let mut grid = HashMap::<_, Vec<usize>>::new();
+    for (i, (point, _)) in stops.iter().enumerate() {
+        grid.entry(cell(point)).or_default().push(i);
+    }
+
+    // union-find over the stops, joining each close pair
+    let mut parent = (0..stops.len()).collect::<Vec<_>>();
+    let is_station_of = |a: &Stop, b: &Stop| b.parent_station.as_deref() == Some(a.id.as_str());
+    for (i, (point, stop)) in stops.iter().enumerate() {
+        let (x, y) = cell(point);
+        let neighbors = (x - 1..=x + 1)
+            .cartesian_product(y - 1..=y + 1)
+            .filter_map(|cell| grid.get(&cell))
+            .flatten();
+        for &j in neighbors {
+            let (other_point, other) = &stops[j];
+            if j <= i || is_station_of(stop, other) || is_station_of(other, stop) {
+                continue;
+            }
+            if radius::distance(point, other_point, radius::Metric::Geodesic) <= threshold {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups = HashMap::<_, Vec<&Stop>>::new();
+    for (i, (_, stop)) in stops.iter().enumerate() {
+        groups.entry(root(&mut parent, i)).or_default().push(stop);
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .sorted_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].id.cmp(&b[0].id)))
+        .collect()
+}
+
+/// Returns the root of `i`'s set in a union-find forest.
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+#[derive(Debug)]
+pub struct ColorIssue {
+    pub route_id: String,
+    /// The column the color is in: `route_color` or `route_text_color`.
+    pub field: &'static str,
+    pub value: String,
+    /// The color written as six uppercase hex digits, if it can be.
+    pub normalized: Option<String>,
+}
+
+/// Normalizes a GTFS color to six uppercase hex digits, or returns `None` if it isn't six hex
+/// digits.
+///
+/// GTFS colors are written without a leading `#`, but one is accepted here, along with
+/// surrounding whitespace, since they are common mistakes with an obvious fix.
+pub fn normalize_color(color: &str) -> Option<String> {
+    let color = color.trim();
+    let color = color.strip_prefix('#').unwrap_or(color);
+    (color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| color.to_ascii_uppercase())
+}
+
+/// Finds routes whose `route_color` or `route_text_color` is malformed, by reading `routes.txt`
+/// directly.
+///
+/// This can't be done on a loaded [`Gtfs`], since a malformed color prevents the feed from
+/// loading at all. Empty colors are allowed, since they take the default, and so are lowercase
+/// hex digits.
+pub fn check_route_colors(routes: impl Read) -> anyhow::Result<Vec<ColorIssue>> {
+    let mut reader = csv::Reader::from_reader(routes);
+    let headers = reader.headers()?.clone();
+    let column = |name| headers.iter().position(|header| header.trim() == name);
+    let route_id = column("route_id");
+    let fields = ["route_color", "route_text_color"]
+        .into_iter()
+        .filter_map(|field| Some((field, column(field)?)))
+        .collect::<Vec<_>>();
+
+    let mut issues = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        for &(field, i) in &fields {
+            let value = record.get(i).unwrap_or("").trim();
+            let normalized = normalize_color(value);
+            let is_valid = normalized
+                .as_deref()
+                .is_some_and(|normalized| normalized.eq_ignore_ascii_case(value));
+            if !value.is_empty() && !is_valid {
+                issues.push(ColorIssue {
+                    route_id: route_id
+                        .and_then(|i| record.get(i))
+                        .unwrap_or("")
+                        .to_owned(),
+                    field,
+                    value: value.to_owned(),
+                    normalized,
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+/// Finds malformed route colors, like [`check_route_colors`], in a zipped feed.
+pub fn check_zipped_route_colors(zip: impl Read + Seek) -> anyhow::Result<Vec<ColorIssue>> {
+    let mut archive = zip::ZipArchive::new(zip)?;
+    // the feed's files may be in a subdirectory of the archive
+    let name = archive
+        .file_names()
+        .find(|name| name.rsplit('/').next() == Some("routes.txt"))
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow::anyhow!("routes.txt not found in feed"))?;
+    check_route_colors(archive.by_name(&name)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_valid_colors() {
+        assert_eq!(normalize_color("FF0000").as_deref(), Some("FF0000"));
+        assert_eq!(normalize_color("00ff7f").as_deref(), Some("00FF7F"));
+        assert_eq!(normalize_color("#1a2B3c").as_deref(), Some("1A2B3C"));
+        assert_eq!(normalize_color(" 123456 ").as_deref(), Some("123456"));
+    }
+
+    #[test]
+    fn normalize_invalid_colors() {
+        for color in [
+            "", "FFF", "#FF00", "FF00001", "GG0000", "FF 000", "##FF0000",
+        ] {
+            assert_eq!(normalize_color(color), None, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn check_route_colors_reports_fixable_and_malformed_colors() {
+        let routes = "route_id,route_color,route_text_color\n\
+                      R1,ff0000,FFFFFF\n\
+                      R2,#00FF00,\n\
+                      R3,red,000000\n";
+        let issues = check_route_colors(routes.as_bytes()).unwrap();
+        let issues = issues
+            .iter()
+            .map(|issue| {
+                (
+                    issue.route_id.as_str(),
+                    issue.field,
+                    issue.normalized.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                ("R2", "route_color", Some("00FF00")),
+                ("R3", "route_color", None),
+            ]
+        );
+    }
+}